@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use primitive_types::{H160, U256};
+
+use crate::block::Block;
+use crate::database::Database;
+use crate::fork::Fork;
+use crate::helpers::{create_address, Convert};
+use crate::precompiles::{get_precompile, NativePrecompiles};
+use crate::receipt::Receipt;
+use crate::transaction::{execute, AccessListItem, Transaction, TransactionError, TxKind, TxType};
+
+// EIP-2929: the accounts and storage slots accessed so far in a transaction.
+// Frames work on a copy which replaces their parent's when they succeed
+#[derive(Debug, Clone, Default)]
+pub struct AccessSet {
+    addresses: HashSet<H160>,
+    storage_keys: HashSet<(H160, U256)>,
+    // Accesses are always tracked, but only charged for when executing a transaction since bare
+    // calls aren't otherwise metered
+    pub charge_cold_access: bool,
+}
+
+impl AccessSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // EIP-2930: everything in the access list starts warm
+    pub fn from_access_list(access_list: &[AccessListItem]) -> Self {
+        let mut access_set = Self::new();
+        for item in access_list {
+            access_set.access_address(item.address);
+            for key in &item.storage_keys {
+                access_set.access_storage_key(item.address, key.to_u256());
+            }
+        }
+        access_set
+    }
+
+    // Marks the address as accessed, returning whether it already was
+    pub fn access_address(&mut self, address: H160) -> bool {
+        !self.addresses.insert(address)
+    }
+
+    // Marks the storage slot as accessed, returning whether it already was
+    pub fn access_storage_key(&mut self, address: H160, key: U256) -> bool {
+        !self.storage_keys.insert((address, key))
+    }
+
+    // Sorted so the same accesses always give the same list
+    pub fn to_access_list(&self) -> Vec<AccessListItem> {
+        let mut items: BTreeMap<H160, BTreeSet<U256>> = BTreeMap::new();
+        for address in &self.addresses {
+            items.entry(*address).or_default();
+        }
+        for (address, key) in &self.storage_keys {
+            items.entry(*address).or_default().insert(*key);
+        }
+
+        items
+            .into_iter()
+            .map(|(address, keys)| AccessListItem {
+                address,
+                storage_keys: keys.into_iter().map(|key| key.to_h256()).collect(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessListResult {
+    pub access_list: Vec<AccessListItem>,
+    // Gas used when the transaction is sent with the access list
+    pub gas_used: u64,
+}
+
+// Like eth_createAccessList: runs the transaction and returns every account and storage slot it touched,
+// except the accounts which are always warm. The state is left as it was. The gas used is that of sending
+// the transaction with the list, as an access list transaction if it was a legacy one
+pub fn create_access_list<D: Database>(
+    tx: &Transaction,
+    block: &Block,
+    state: &mut D,
+    native_precompiles: &NativePrecompiles,
+) -> Result<AccessListResult, TransactionError> {
+    let (_, access_set) = execute_and_revert(tx, block, state, native_precompiles)?;
+
+    let recipient = match tx.to {
+        TxKind::Call(to) => to,
+        TxKind::Create => create_address(tx.from, tx.nonce.into()),
+    };
+    let access_list: Vec<AccessListItem> = access_set
+        .to_access_list()
+        .into_iter()
+        .filter(|item| {
+            // the coinbase is only warm since EIP-3651
+            let always_warm = item.address == tx.from
+                || item.address == recipient
+                || (item.address == block.coinbase && block.fork.is_enabled(Fork::Shanghai))
+                || get_precompile(item.address, block.fork).is_some();
+            !(always_warm && item.storage_keys.is_empty())
+        })
+        .collect();
+
+    let mut tx = tx.clone();
+    // legacy transactions can't carry an access list
+    if tx.tx_type == TxType::Legacy {
+        tx.tx_type = TxType::AccessList;
+    }
+    tx.access_list = access_list.clone();
+    let (receipt, _) = execute_and_revert(&tx, block, state, native_precompiles)?;
+
+    Ok(AccessListResult {
+        access_list,
+        gas_used: receipt.gas_used,
+    })
+}
+
+fn execute_and_revert<D: Database>(
+    tx: &Transaction,
+    block: &Block,
+    state: &mut D,
+    native_precompiles: &NativePrecompiles,
+) -> Result<(Receipt, AccessSet), TransactionError> {
+    let checkpoint = state.checkpoint();
+    let result = execute(tx, block, state, native_precompiles);
+    state.revert(checkpoint);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+    use crate::transaction::{transact, TxFees};
+    use primitive_types::H256;
+
+    fn sender() -> H160 {
+        H160::repeat_byte(0x11)
+    }
+
+    fn recipient() -> H160 {
+        H160::repeat_byte(0x22)
+    }
+
+    fn coinbase() -> H160 {
+        H160::repeat_byte(0x33)
+    }
+
+    fn block(fork: Fork) -> Block {
+        Block {
+            coinbase: coinbase(),
+            fork,
+            ..Default::default()
+        }
+    }
+
+    // BALANCE of 0x44, the coinbase, 0x01 and the executing account, then SLOAD(1)
+    fn code() -> Vec<u8> {
+        let balance_of_coinbase = format!("73{}3150", "33".repeat(20));
+        let code = ["60443150", &balance_of_coinbase, "60013150", "303150", "60015450", "00"].concat();
+        hex::decode(code).unwrap()
+    }
+
+    fn tx(tx_type: TxType, to: TxKind) -> Transaction {
+        let fees = match tx_type {
+            TxType::Legacy => TxFees::Legacy { gas_price: 1.into() },
+            _ => TxFees::Dynamic {
+                max_fee_per_gas: 1.into(),
+                max_priority_fee_per_gas: 1.into(),
+            },
+        };
+        Transaction {
+            tx_type,
+            from: sender(),
+            to,
+            value: U256::zero(),
+            data: Vec::new(),
+            gas_limit: 100_000,
+            fees,
+            nonce: 0,
+            access_list: Vec::new(),
+            max_fee_per_blob_gas: U256::zero(),
+            blob_versioned_hashes: Vec::new(),
+            authorization_list: Vec::new(),
+        }
+    }
+
+    fn state() -> State {
+        let mut state = State::new();
+        state.add_account(sender(), 1_000_000.into(), Vec::new());
+        state.add_account(recipient(), 0.into(), code());
+        state
+    }
+
+    fn item(address: H160, storage_keys: Vec<u64>) -> AccessListItem {
+        AccessListItem {
+            address,
+            storage_keys: storage_keys.into_iter().map(H256::from_low_u64_be).collect(),
+        }
+    }
+
+    // The gas used when actually sending the transaction with the access list
+    fn gas_used_with(mut tx: Transaction, fork: Fork, access_list: Vec<AccessListItem>) -> u64 {
+        if tx.tx_type == TxType::Legacy {
+            tx.tx_type = TxType::AccessList;
+        }
+        tx.access_list = access_list;
+        transact(&tx, &block(fork), &mut state(), &NativePrecompiles::new()).unwrap().gas_used
+    }
+
+    fn assert_access_list(tx: Transaction, fork: Fork, expected: Vec<AccessListItem>) {
+        let mut state = state();
+
+        let res = create_access_list(&tx, &block(fork), &mut state, &NativePrecompiles::new()).unwrap();
+
+        assert_eq!(res.access_list, expected);
+        assert_eq!(res.gas_used, gas_used_with(tx, fork, expected));
+        assert_eq!(state.nonce(sender()).unwrap(), 0);
+    }
+
+    #[test]
+    fn legacy_transactions_are_priced_as_access_list_transactions() {
+        let tx = tx(TxType::Legacy, TxKind::Call(recipient()));
+        let expected = vec![item(H160::from_low_u64_be(0x44), vec![]), item(recipient(), vec![1])];
+
+        assert_access_list(tx, Fork::Prague, expected);
+    }
+
+    #[test]
+    fn dynamic_fee_transactions_keep_their_type() {
+        let tx = tx(TxType::DynamicFee, TxKind::Call(recipient()));
+        let expected = vec![item(H160::from_low_u64_be(0x44), vec![]), item(recipient(), vec![1])];
+
+        assert_access_list(tx, Fork::Prague, expected);
+    }
+
+    #[test]
+    fn the_created_contract_is_left_out() {
+        let mut tx = tx(TxType::DynamicFee, TxKind::Create);
+        tx.data = code();
+        let created = create_address(sender(), 0.into());
+        let expected = vec![item(H160::from_low_u64_be(0x44), vec![]), item(created, vec![1])];
+
+        assert_access_list(tx.clone(), Fork::Prague, expected);
+
+        // without reading its storage the contract isn't listed at all
+        // BALANCE of 0x44 and the executing account
+        tx.data = hex::decode("6044315030315000").unwrap();
+        assert_access_list(tx, Fork::Prague, vec![item(H160::from_low_u64_be(0x44), vec![])]);
+    }
+
+    #[test]
+    fn the_coinbase_is_listed_before_shanghai() {
+        let tx = tx(TxType::DynamicFee, TxKind::Call(recipient()));
+        let expected = vec![
+            item(H160::from_low_u64_be(0x44), vec![]),
+            item(recipient(), vec![1]),
+            item(coinbase(), vec![]),
+        ];
+
+        assert_access_list(tx, Fork::London, expected);
+    }
+}
@@ -0,0 +1,143 @@
+use primitive_types::{H160, H256, U256};
+use rlp::RlpStream;
+use sha3::{Digest, Keccak256};
+
+use crate::precompiles::ecrecover::recover_address;
+
+// EIP-7702: authorizations are signed over keccak256(MAGIC ++ rlp([chain_id, address, nonce]))
+const MAGIC: u8 = 0x05;
+const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+
+// secp256k1n / 2, higher s values are rejected as in EIP-2
+const SECP256K1N_HALF: U256 = U256([
+    0xdfe92f46681b20a0,
+    0x5d576e7357a4501d,
+    0xffffffffffffffff,
+    0x7fffffffffffffff,
+]);
+
+// EIP-7702: lets the signing account delegate to the code at address, until it authorizes another
+#[derive(Debug, Clone)]
+pub struct Authorization {
+    // Zero is valid on any chain
+    pub chain_id: U256,
+    pub address: H160,
+    pub nonce: u64,
+    pub y_parity: u8,
+    pub r: U256,
+    pub s: U256,
+}
+
+impl Authorization {
+    // The account which signed the authorization, None if the signature is invalid
+    pub fn authority(&self) -> Option<H160> {
+        if self.y_parity > 1 || self.s > SECP256K1N_HALF {
+            return None;
+        }
+
+        let mut signature = [0; 64];
+        self.r.to_big_endian(&mut signature[..32]);
+        self.s.to_big_endian(&mut signature[32..]);
+
+        recover_address(self.signing_hash().as_bytes(), self.y_parity, &signature)
+    }
+
+    // The hash the authority signs
+    pub fn signing_hash(&self) -> H256 {
+        let mut stream = RlpStream::new_list(3);
+        stream.append(&self.chain_id);
+        stream.append(&self.address);
+        stream.append(&self.nonce);
+
+        let mut hasher = Keccak256::new();
+        hasher.update([MAGIC]);
+        hasher.update(stream.out());
+        H256::from_slice(&hasher.finalize())
+    }
+}
+
+// The code of an account which delegated to address
+pub fn delegation_designator(address: H160) -> Vec<u8> {
+    let mut code = DELEGATION_PREFIX.to_vec();
+    code.extend_from_slice(address.as_bytes());
+    code
+}
+
+// The address an account's code delegates to, if it is a delegation designator
+pub fn delegated_address(code: &[u8]) -> Option<H160> {
+    if code.len() == DELEGATION_PREFIX.len() + 20 && code.starts_with(&DELEGATION_PREFIX) {
+        Some(H160::from_slice(&code[DELEGATION_PREFIX.len()..]))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[0x42; 32]).unwrap()
+    }
+
+    fn key_address(key: &SigningKey) -> H160 {
+        let point = key.verifying_key().to_encoded_point(false);
+        H160::from_slice(&Keccak256::digest(&point.as_bytes()[1..])[12..])
+    }
+
+    fn signed(chain_id: u64) -> Authorization {
+        let mut authorization = Authorization {
+            chain_id: chain_id.into(),
+            address: H160::repeat_byte(0xaa),
+            nonce: 0,
+            y_parity: 0,
+            r: U256::zero(),
+            s: U256::zero(),
+        };
+        let (signature, recovery_id) = key()
+            .sign_prehash_recoverable(authorization.signing_hash().as_bytes())
+            .unwrap();
+        authorization.r = U256::from_big_endian(&signature.r().to_bytes());
+        authorization.s = U256::from_big_endian(&signature.s().to_bytes());
+        authorization.y_parity = recovery_id.to_byte();
+        authorization
+    }
+
+    #[test]
+    fn recovers_the_signing_account() {
+        assert_eq!(signed(1).authority(), Some(key_address(&key())));
+        assert_eq!(signed(0).authority(), Some(key_address(&key())));
+    }
+
+    #[test]
+    fn signature_covers_the_chain_id() {
+        let mut authorization = signed(1);
+        authorization.chain_id = 2.into();
+
+        assert_ne!(authorization.authority(), Some(key_address(&key())));
+    }
+
+    #[test]
+    fn rejects_high_s_and_invalid_y_parity() {
+        // (r, n - s) with the other parity is the same signature, which EIP-2 rules out
+        let mut authorization = signed(1);
+        authorization.s = SECP256K1N_HALF * 2 + 1 - authorization.s;
+        authorization.y_parity ^= 1;
+        assert_eq!(authorization.authority(), None);
+
+        let mut authorization = signed(1);
+        authorization.y_parity = 2;
+        assert_eq!(authorization.authority(), None);
+    }
+
+    #[test]
+    fn designator_round_trips() {
+        let code = delegation_designator(H160::repeat_byte(0xaa));
+
+        assert_eq!(code.len(), 23);
+        assert_eq!(delegated_address(&code), Some(H160::repeat_byte(0xaa)));
+        assert_eq!(delegated_address(&code[..22]), None);
+        assert_eq!(delegated_address(&[0xef, 0x01, 0x01]), None);
+    }
+}
@@ -1,32 +1,149 @@
-#[derive(Copy, Clone)]
-pub struct Block<'a> {
-    pub coinbase: &'a [u8],
-    pub timestamp: &'a [u8],
-    pub number: &'a [u8],
-    pub difficulty: &'a [u8],
-    pub gaslimit: &'a [u8],
-    pub chainid: &'a [u8],
-    pub basefee: &'a [u8],
+use primitive_types::{H160, H256, U256};
+use serde::Deserialize;
+
+use crate::consts::{
+    BLOB_BASE_FEE_UPDATE_FRACTION, BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE, GWEI, MIN_BASE_FEE_PER_BLOB_GAS,
+};
+use crate::fees::{next_base_fee, ELASTICITY_MULTIPLIER};
+use crate::fork::Fork;
+use crate::helpers::{fake_exponential, Convert};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "BlockData")]
+pub struct Block {
+    pub coinbase: H160,
+    pub timestamp: U256,
+    pub number: U256,
+    pub parent_hash: H256,
+    pub difficulty: U256,
+    pub prevrandao: H256,
+    pub gaslimit: U256,
+    pub chainid: U256,
+    pub basefee: U256,
+    pub excess_blob_gas: u64,
+    pub parent_beacon_block_root: H256,
+    pub fork: Fork,
+}
+
+impl Block {
+    // blob_basefee = fake_exponential(MIN_BASE_FEE_PER_BLOB_GAS, excess_blob_gas, BLOB_BASE_FEE_UPDATE_FRACTION),
+    // with the larger fraction of EIP-7691 from Prague
+    pub fn blob_basefee(&self) -> U256 {
+        let update_fraction = if self.fork.is_enabled(Fork::Prague) {
+            BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE
+        } else {
+            BLOB_BASE_FEE_UPDATE_FRACTION
+        };
+        fake_exponential(MIN_BASE_FEE_PER_BLOB_GAS, self.excess_blob_gas, update_fraction)
+    }
+
+    // The base fee of the next block, given the gas used by this one
+    pub fn next_basefee(&self, gas_used: u64) -> U256 {
+        let gas_target = self.gas_limit() / ELASTICITY_MULTIPLIER;
+        next_base_fee(self.basefee, gas_used, gas_target)
+    }
+
+    // Gas is counted in u64, so a larger limit is as good as u64::MAX
+    pub fn gas_limit(&self) -> u64 {
+        u64::try_from(self.gaslimit).unwrap_or(u64::MAX)
+    }
+}
+
+// EIP-4895: a withdrawal from the beacon chain, which credits an address without running any code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: H160,
+    // In gwei
+    pub amount: u64,
+}
+
+impl Withdrawal {
+    pub fn amount_wei(&self) -> U256 {
+        U256::from(self.amount) * U256::from(GWEI)
+    }
 }
 
-impl<'a> Block<'a> {
-    pub fn new(
-        coinbase: &'a [u8],
-        timestamp: &'a [u8],
-        number: &'a [u8],
-        difficulty: &'a [u8],
-        gaslimit: &'a [u8],
-        chainid: &'a [u8],
-        basefee: &'a [u8],
-    ) -> Self {
+// Hex strings as they appear in test fixtures, which are often shorter than the full width
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct BlockData {
+    coinbase: Option<String>,
+    timestamp: Option<String>,
+    number: Option<String>,
+    parent_hash: Option<String>,
+    difficulty: Option<String>,
+    prevrandao: Option<String>,
+    gaslimit: Option<String>,
+    chainid: Option<String>,
+    basefee: Option<String>,
+    excess_blob_gas: Option<String>,
+    parent_beacon_block_root: Option<String>,
+    fork: Option<Fork>,
+}
+
+impl From<BlockData> for Block {
+    fn from(data: BlockData) -> Self {
+        let to_u256 = |value: Option<String>| value.map(|v| v.to_u256()).unwrap_or_default();
+        let to_h256 = |value: Option<String>| value.map(|v| v.to_h256()).unwrap_or_default();
+
         Self {
-            coinbase,
-            timestamp,
-            number,
-            difficulty,
-            gaslimit,
-            chainid,
-            basefee,
+            coinbase: data.coinbase.map(|v| v.to_h160()).unwrap_or_default(),
+            timestamp: to_u256(data.timestamp),
+            number: to_u256(data.number),
+            parent_hash: to_h256(data.parent_hash),
+            difficulty: to_u256(data.difficulty),
+            prevrandao: to_h256(data.prevrandao),
+            gaslimit: to_u256(data.gaslimit),
+            chainid: to_u256(data.chainid),
+            basefee: to_u256(data.basefee),
+            excess_blob_gas: to_u256(data.excess_blob_gas).try_into().unwrap_or(u64::MAX),
+            parent_beacon_block_root: to_h256(data.parent_beacon_block_root),
+            fork: data.fork.unwrap_or_default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_basefee_targets_half_the_gas_limit() {
+        let block = Block {
+            gaslimit: 20_000_000.into(),
+            basefee: 1_000_000_000.into(),
+            ..Default::default()
+        };
+
+        assert_eq!(block.next_basefee(10_000_000), 1_000_000_000.into());
+        assert_eq!(block.next_basefee(20_000_000), 1_125_000_000.into());
+    }
+
+    #[test]
+    fn gas_limit_saturates_rather_than_truncating() {
+        let block = Block {
+            gaslimit: U256::from(u64::MAX) + 2,
+            basefee: 1_000_000_000.into(),
+            ..Default::default()
+        };
+
+        assert_eq!(block.gas_limit(), u64::MAX);
+        // the target is far above anything used, so the base fee falls by the most
+        assert_eq!(block.next_basefee(0), 875_000_000.into());
+    }
+
+    #[test]
+    fn blob_basefee_updates_more_slowly_from_prague() {
+        let block = |fork| Block {
+            excess_blob_gas: 10_000_000,
+            fork,
+            ..Default::default()
+        };
+
+        assert_eq!(block(Fork::Cancun).blob_basefee(), 19.into());
+        assert_eq!(block(Fork::Prague).blob_basefee(), 7.into());
+        assert_eq!(Block::default().blob_basefee(), 1.into());
+    }
+}
@@ -1 +1,32 @@
-pub const WORD_BYTES: usize = 32;
\ No newline at end of file
+pub const WORD_BYTES: usize = 32;
+
+// EIP-170
+pub const MAX_CODE_SIZE: usize = 0x6000;
+// EIP-3860
+pub const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE;
+pub const INITCODE_WORD_COST: u64 = 2;
+pub const CODE_DEPOSIT_COST: u64 = 200;
+
+// EIP-4895 withdrawal amounts are in gwei
+pub const GWEI: u64 = 1_000_000_000;
+
+// EIP-2929
+pub const COLD_ACCOUNT_ACCESS_COST: u64 = 2600;
+pub const COLD_SLOAD_COST: u64 = 2100;
+pub const WARM_STORAGE_READ_COST: u64 = 100;
+
+// EIP-3529
+pub const MAX_REFUND_QUOTIENT: u64 = 5;
+
+// EIP-7702
+pub const PER_EMPTY_ACCOUNT_COST: u64 = 25000;
+pub const PER_AUTH_BASE_COST: u64 = 12500;
+
+// EIP-4844
+pub const GAS_PER_BLOB: u64 = 1 << 17;
+pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+pub const MIN_BASE_FEE_PER_BLOB_GAS: u64 = 1;
+pub const BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 3338477;
+
+// EIP-7691
+pub const BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE: u64 = 5007716;
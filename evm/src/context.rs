@@ -1,7 +1,11 @@
-use primitive_types::{U256, H160};
+use primitive_types::{U256, H160, H256};
+use crate::access_list::AccessSet;
+use crate::helpers::{arr_slice_extend, Convert};
+use crate::precompiles::NativePrecompiles;
+use crate::database::Database;
 use crate::state::State;
 
-pub struct Context<'a> {
+pub struct Context<'a, D = State> {
     pub address: H160,
     pub caller: H160,
     pub origin: H160,
@@ -9,12 +13,18 @@ pub struct Context<'a> {
     // TODO: update to U256, handle overlap with State.Account.balance -> maybe not
     pub value: U256,
     pub call_data: &'a String,
-    pub state: State,
+    // Shared by every frame of a call, see Database for how failed frames are undone
+    pub state: &'a mut D,
     pub is_static: bool,
+    pub blob_versioned_hashes: Vec<H256>,
+    pub gas_limit: u64,
+    pub native_precompiles: NativePrecompiles,
+    pub access_set: AccessSet,
 }
 
 // TODO: remove lifetime parameter where possible
-impl<'a> Context<'a> {
+impl<'a, D: Database> Context<'a, D> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         address: H160,
         caller: H160,
@@ -23,7 +33,7 @@ impl<'a> Context<'a> {
         value: U256,
         // TODO: update data to call_data and store as hex not string
         call_data: &'a String,
-        state: State,
+        state: &'a mut D,
         is_static: bool,
     ) -> Self {
         Self {
@@ -35,26 +45,55 @@ impl<'a> Context<'a> {
             call_data,
             state,
             is_static,
+            blob_versioned_hashes: Vec::new(),
+            gas_limit: u64::MAX,
+            native_precompiles: NativePrecompiles::new(),
+            access_set: AccessSet::new(),
+        }
+    }
+
+    // The context of a nested call or creation, which shares the fields of the transaction with its parent
+    pub fn child<'b>(
+        &'b mut self,
+        address: H160,
+        caller: H160,
+        value: U256,
+        call_data: &'b String,
+        is_static: bool,
+        gas_limit: u64,
+    ) -> Context<'b, D> {
+        Context {
+            address,
+            caller,
+            origin: self.origin,
+            gasprice: self.gasprice,
+            value,
+            call_data,
+            state: &mut *self.state,
+            is_static,
+            blob_versioned_hashes: self.blob_versioned_hashes.clone(),
+            gas_limit,
+            native_precompiles: self.native_precompiles.clone(),
+            access_set: self.access_set.clone(),
+        }
+    }
+
+    pub fn blob_hash(&self, index: U256) -> U256 {
+        if index < self.blob_versioned_hashes.len().into() {
+            self.blob_versioned_hashes[index.as_usize()].to_u256()
+        } else {
+            U256::zero()
         }
     }
 
     pub fn calldata_size(&self) -> U256 {
-        let call_data_size = hex::decode(&self.call_data).unwrap().len();
+        let call_data_size = hex::decode(self.call_data).unwrap().len();
         call_data_size.into()
     }
 
     pub fn load_calldata(&self, byte_offset: usize, target_size: usize) -> U256 {
-        let call_data = hex::decode(&self.call_data).unwrap();
-        let mut res: Vec<u8> = vec![0; target_size];
-
-        for i in 0..target_size {
-            let data_index = i + byte_offset;
-            if data_index < call_data.len() {
-                let val = call_data[data_index];
-                res[i] = val;
-            }
-        }
+        let call_data = hex::decode(self.call_data).unwrap();
 
-        U256::from_big_endian(&res)
+        arr_slice_extend(&call_data, byte_offset, target_size)
     }
 }
@@ -0,0 +1,58 @@
+use std::fmt::Debug;
+
+use primitive_types::{H160, H256, U256};
+use sha3::{Digest, Keccak256};
+
+// Everything the interpreter reads from or writes to the world state.
+// Call frames share the database: each takes a checkpoint before it runs and reverts to it when it
+// fails, so implementations have to journal their changes
+pub trait Database {
+    // Any error aborts the transaction being executed, e.g. a storage layer failing to load an account
+    type Error: Debug;
+
+    fn balance(&self, address: H160) -> Result<U256, Self::Error>;
+
+    fn code(&self, address: H160) -> Result<Vec<u8>, Self::Error>;
+
+    // Accounts without code hash to zero
+    fn code_hash(&self, address: H160) -> Result<H256, Self::Error> {
+        let code = self.code(address)?;
+        if code.is_empty() {
+            Ok(H256::zero())
+        } else {
+            Ok(H256::from_slice(&Keccak256::digest(code)))
+        }
+    }
+
+    fn storage(&self, address: H160, key: U256) -> Result<U256, Self::Error>;
+
+    fn nonce(&self, address: H160) -> Result<u64, Self::Error>;
+
+    fn block_hash(&self, number: U256) -> Result<H256, Self::Error>;
+
+    fn set_storage(&mut self, address: H160, key: U256, value: U256) -> Result<(), Self::Error>;
+
+    fn set_code(&mut self, address: H160, code: Vec<u8>) -> Result<(), Self::Error>;
+
+    fn set_nonce(&mut self, address: H160, nonce: u64) -> Result<(), Self::Error>;
+
+    fn increment_balance(&mut self, address: H160, amount: U256) -> Result<(), Self::Error>;
+
+    // Callers check the balance is sufficient first, an underflow is an error rather than wrapping
+    fn decrement_balance(&mut self, address: H160, amount: U256) -> Result<(), Self::Error>;
+
+    // Removes the account, returning its balance
+    fn destruct_account(&mut self, address: H160) -> Result<U256, Self::Error>;
+
+    // The root of the state trie, see the trie module for building one
+    fn state_root(&self) -> Result<H256, Self::Error>;
+
+    // Marks the current state so later changes can be undone, checkpoints nest like the frames taking them
+    fn checkpoint(&mut self) -> usize;
+
+    // Undoes every change made since the checkpoint, including those of checkpoints taken after it
+    fn revert(&mut self, checkpoint: usize);
+
+    // Keeps the changes made since the checkpoint, reverting to an earlier checkpoint still undoes them
+    fn commit(&mut self, checkpoint: usize);
+}
@@ -1,12 +1,31 @@
-use crate::consts::WORD_BYTES;
-use crate::context::Context;
-use crate::machine::{ControlFlow, EvmError, ExitSuccess, Log, Machine};
+use crate::authorization::delegated_address;
+use crate::consts::{
+    COLD_ACCOUNT_ACCESS_COST, COLD_SLOAD_COST, INITCODE_WORD_COST, MAX_INITCODE_SIZE,
+    WARM_STORAGE_READ_COST, WORD_BYTES,
+};
+use crate::database::Database;
+use crate::fork::Fork;
+use crate::gas::all_but_one_64th;
+use crate::machine::{ControlFlow, EvmError, EvmResult, ExitSuccess, Log, Machine};
 use crate::opcode::Opcode;
+use crate::precompiles::{get_precompile, NativePrecompile, Precompile};
+use crate::system_calls::history_block_hash;
 use crate::{evm, helpers::*};
-use primitive_types::{U256};
+use primitive_types::{H160, U256};
 use sha3::{Digest, Keccak256};
+use std::fmt::Debug;
+
+// Unwraps the result of a database access, halting the frame if it failed
+macro_rules! try_database {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(err) => return exit_error(database_error(err)),
+        }
+    };
+}
 
-pub fn eval(machine: &mut Machine) -> ControlFlow {
+pub fn eval<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let opcode = machine.opcode();
     if machine.context.is_static && !Opcode::is_static(opcode) {
         return exit_error(EvmError::OpcodeNotStatic(opcode));
@@ -64,6 +83,8 @@ pub fn eval(machine: &mut Machine) -> ControlFlow {
         Opcode::CHAINID => chainid(machine),
         Opcode::SELFBALANCE => selfbalance(machine),
         Opcode::BASEFEE => basefee(machine),
+        Opcode::BLOBHASH => blobhash(machine),
+        Opcode::BLOBBASEFEE => blobbasefee(machine),
         Opcode::POP => eval_pop(machine),
         Opcode::MLOAD => mload(machine),
         Opcode::MSTORE => mstore(machine),
@@ -76,6 +97,7 @@ pub fn eval(machine: &mut Machine) -> ControlFlow {
         Opcode::MSIZE => msize(machine),
         Opcode::GAS => gas(machine),
         Opcode::JUMPDEST => jumpdest(machine),
+        Opcode::PUSH0 => push0(machine),
         Opcode::PUSH1..=Opcode::PUSH32 => eval_push(machine),
         Opcode::DUP1..=Opcode::DUP16 => dup(machine),
         Opcode::SWAP1..=Opcode::SWAP16 => swap(machine),
@@ -84,6 +106,7 @@ pub fn eval(machine: &mut Machine) -> ControlFlow {
         Opcode::CALL => call(machine),
         Opcode::RETURN => eval_return(machine),
         Opcode::DELEGATECALL => delegatecall(machine),
+        Opcode::CREATE2 => create2(machine),
         Opcode::STATICCALL => staticcall(machine),
         Opcode::REVERT => revert(machine),
         Opcode::INVALID => invalid(machine),
@@ -97,11 +120,11 @@ pub fn eval(machine: &mut Machine) -> ControlFlow {
 // TODO: add and handle as_usize or fail
 // TODO: add 1024 stack limit
 
-fn stop(_machine: &mut Machine) -> ControlFlow {
+fn stop<D: Database>(_machine: &mut Machine<D>) -> ControlFlow {
     exit_success(ExitSuccess::Stop)
 }
 
-fn add(machine: &mut Machine) -> ControlFlow {
+fn add<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = a.overflowing_add(b).0;
@@ -110,7 +133,7 @@ fn add(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn mul(machine: &mut Machine) -> ControlFlow {
+fn mul<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = a.overflowing_mul(b).0;
@@ -119,7 +142,7 @@ fn mul(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn sub(machine: &mut Machine) -> ControlFlow {
+fn sub<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = a.overflowing_sub(b).0;
@@ -128,7 +151,7 @@ fn sub(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn div(machine: &mut Machine) -> ControlFlow {
+fn div<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = a.checked_div(b);
@@ -140,7 +163,7 @@ fn div(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn sdiv(machine: &mut Machine) -> ControlFlow {
+fn sdiv<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let mut a = machine.stack.pop().unwrap();
     let mut b = machine.stack.pop().unwrap();
 
@@ -179,7 +202,7 @@ fn sdiv(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn modulus(machine: &mut Machine) -> ControlFlow {
+fn modulus<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = a.checked_rem(b);
@@ -191,7 +214,7 @@ fn modulus(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn smodulus(machine: &mut Machine) -> ControlFlow {
+fn smodulus<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let mut a = machine.stack.pop().unwrap();
     let mut b = machine.stack.pop().unwrap();
 
@@ -223,7 +246,7 @@ fn smodulus(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn add_modulus(machine: &mut Machine) -> ControlFlow {
+fn add_modulus<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let c = machine.stack.pop().unwrap();
@@ -236,7 +259,7 @@ fn add_modulus(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn mul_modulus(machine: &mut Machine) -> ControlFlow {
+fn mul_modulus<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let c = machine.stack.pop().unwrap();
@@ -254,7 +277,7 @@ fn mul_modulus(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn exp(machine: &mut Machine) -> ControlFlow {
+fn exp<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = a.overflowing_pow(b).0;
@@ -280,7 +303,7 @@ fn exp(machine: &mut Machine) -> ControlFlow {
 // = 11111110 | 1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111100000000
 // = 1111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111111110
 // = 0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFE
-fn sign_extend(machine: &mut Machine) -> ControlFlow {
+fn sign_extend<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let num_bytes = machine.stack.pop().unwrap();
     let int_to_extend = machine.stack.pop().unwrap();
 
@@ -312,7 +335,7 @@ fn sign_extend(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn lt(machine: &mut Machine) -> ControlFlow {
+fn lt<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = (a < b) as u32;
@@ -321,7 +344,7 @@ fn lt(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn gt(machine: &mut Machine) -> ControlFlow {
+fn gt<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
     let res = (a > b) as u32;
@@ -330,7 +353,7 @@ fn gt(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn slt(machine: &mut Machine) -> ControlFlow {
+fn slt<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let mut a = machine.stack.pop().unwrap();
     let mut b = machine.stack.pop().unwrap();
 
@@ -368,7 +391,7 @@ fn slt(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn sgt(machine: &mut Machine) -> ControlFlow {
+fn sgt<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let mut a = machine.stack.pop().unwrap();
     let mut b = machine.stack.pop().unwrap();
 
@@ -406,7 +429,7 @@ fn sgt(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn eq(machine: &mut Machine) -> ControlFlow {
+fn eq<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
 
@@ -419,7 +442,7 @@ fn eq(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn iszero(machine: &mut Machine) -> ControlFlow {
+fn iszero<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
 
     if a == U256::zero() {
@@ -431,7 +454,7 @@ fn iszero(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn not(machine: &mut Machine) -> ControlFlow {
+fn not<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
 
     machine.stack.push(!a);
@@ -439,7 +462,7 @@ fn not(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn byte(machine: &mut Machine) -> ControlFlow {
+fn byte<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let byte_offset = machine.stack.pop().unwrap();
     let value = machine.stack.pop().unwrap();
 
@@ -457,7 +480,7 @@ fn byte(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn and(machine: &mut Machine) -> ControlFlow {
+fn and<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
 
@@ -466,7 +489,7 @@ fn and(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn or(machine: &mut Machine) -> ControlFlow {
+fn or<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
 
@@ -475,7 +498,7 @@ fn or(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn xor(machine: &mut Machine) -> ControlFlow {
+fn xor<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let b = machine.stack.pop().unwrap();
 
@@ -484,7 +507,7 @@ fn xor(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn shl(machine: &mut Machine) -> ControlFlow {
+fn shl<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let shift = machine.stack.pop().unwrap();
     let value = machine.stack.pop().unwrap();
 
@@ -494,7 +517,7 @@ fn shl(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn shr(machine: &mut Machine) -> ControlFlow {
+fn shr<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let shift = machine.stack.pop().unwrap();
     let value = machine.stack.pop().unwrap();
 
@@ -504,7 +527,7 @@ fn shr(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn sar(machine: &mut Machine) -> ControlFlow {
+fn sar<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     // shift value is unsigned
     let shift = machine.stack.pop().unwrap();
     // value is signed
@@ -527,7 +550,7 @@ fn sar(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn keccak256(machine: &mut Machine) -> ControlFlow {
+fn keccak256<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let offset = machine.stack.pop().unwrap();
     let size = machine.stack.pop().unwrap();
 
@@ -539,7 +562,7 @@ fn keccak256(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn address(machine: &mut Machine) -> ControlFlow {
+fn address<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
         .push(machine.context.address.to_u256());
@@ -547,16 +570,20 @@ fn address(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn balance(machine: &mut Machine) -> ControlFlow {
+fn balance<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let address = machine.stack.pop().unwrap().to_h160();
-    let balance = machine.context.state.get_account_balance(address);
+
+    if let Err(err) = access_account(machine, address) {
+        return exit_error(err);
+    }
+    let balance = try_database!(machine.context.state.balance(address));
 
     machine.stack.push(balance);
 
     ControlFlow::Continue(1)
 }
 
-fn origin(machine: &mut Machine) -> ControlFlow {
+fn origin<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
         .push(machine.context.origin.to_u256());
@@ -564,7 +591,7 @@ fn origin(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn caller(machine: &mut Machine) -> ControlFlow {
+fn caller<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
         .push(machine.context.caller.to_u256());
@@ -572,13 +599,13 @@ fn caller(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn callvalue(machine: &mut Machine) -> ControlFlow {
+fn callvalue<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine.stack.push(machine.context.value);
 
     ControlFlow::Continue(1)
 }
 
-fn calldataload(machine: &mut Machine) -> ControlFlow {
+fn calldataload<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let byte_offset = machine.stack.pop().unwrap();
 
     machine.stack.push(
@@ -590,14 +617,14 @@ fn calldataload(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn calldatasize(machine: &mut Machine) -> ControlFlow {
+fn calldatasize<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine.stack.push(machine.context.calldata_size());
 
     ControlFlow::Continue(1)
 }
 
 // TODO: move all possible .as_usize()'s to the initial values
-fn calldatacopy(machine: &mut Machine) -> ControlFlow {
+fn calldatacopy<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let dest_offset = machine.stack.pop().unwrap();
     let offset = machine.stack.pop().unwrap();
     let size = machine.stack.pop().unwrap();
@@ -613,13 +640,13 @@ fn calldatacopy(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn codesize(machine: &mut Machine) -> ControlFlow {
+fn codesize<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine.stack.push(machine.code.len().into());
 
     ControlFlow::Continue(1)
 }
 
-fn codecopy(machine: &mut Machine) -> ControlFlow {
+fn codecopy<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let dest_offset = machine.stack.pop().unwrap().as_usize();
     let offset = machine.stack.pop().unwrap().as_usize();
     let size = machine.stack.pop().unwrap().as_usize();
@@ -631,12 +658,32 @@ fn codecopy(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn blockhash(_machine: &mut Machine) -> ControlFlow {
-    // TODO: implement blockhash properly
+fn blockhash<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let number = machine.stack.pop().unwrap();
+    let current = machine.block.number;
+
+    // only the 256 most recent complete blocks are available
+    if number < current && current - number <= 256.into() {
+        // EIP-2935: the history contract is used once it has stored the hash, for older blocks the
+        // database still has to provide it
+        let stored = if machine.block.fork.is_enabled(Fork::Prague) {
+            try_database!(history_block_hash(&*machine.context.state, number))
+        } else {
+            None
+        };
+        let hash = match stored {
+            Some(hash) => hash,
+            None => try_database!(machine.context.state.block_hash(number)),
+        };
+        machine.stack.push(hash.to_u256());
+    } else {
+        machine.stack.push(0.into());
+    }
+
     ControlFlow::Continue(1)
 }
 
-fn gasprice(machine: &mut Machine) -> ControlFlow {
+fn gasprice<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     // TODO: implement gas price properly
     machine
         .stack
@@ -645,23 +692,31 @@ fn gasprice(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn extcodesize(machine: &mut Machine) -> ControlFlow {
+fn extcodesize<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let address = machine.stack.pop().unwrap().to_h160();
 
-    let code = machine.context.state.get_account_code(address);
+    if let Err(err) = access_account(machine, address) {
+        return exit_error(err);
+    }
+
+    let code = try_database!(machine.context.state.code(address));
 
     machine.stack.push(code.len().into());
 
     ControlFlow::Continue(1)
 }
 
-fn extcodecopy(machine: &mut Machine) -> ControlFlow {
+fn extcodecopy<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let address = machine.stack.pop().unwrap().to_h160();
     let dest_offset = machine.stack.pop().unwrap().as_usize();
     let offset = machine.stack.pop().unwrap().as_usize();
     let size = machine.stack.pop().unwrap().as_usize();
 
-    let account_code = machine.context.state.get_account_code(address);
+    if let Err(err) = access_account(machine, address) {
+        return exit_error(err);
+    }
+
+    let account_code = try_database!(machine.context.state.code(address));
     let code = arr_slice_extend(&account_code[..], offset, size);
 
     // TODO: set vec<u8> instead of U256 => code could be longer. update in other place as well
@@ -669,113 +724,147 @@ fn extcodecopy(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn extcodehash(machine: &mut Machine) -> ControlFlow {
+fn extcodehash<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let address = machine.stack.pop().unwrap().to_h160();
 
-    let account_code = machine.context.state.get_account_code(address);
-    if account_code.len() == 0 {
-        machine.stack.push(0.into());
-    } else {
-        let hashed_code = Keccak256::digest(account_code);
-        machine.stack.push(U256::from_big_endian(&hashed_code));
+    if let Err(err) = access_account(machine, address) {
+        return exit_error(err);
     }
 
+    let code_hash = try_database!(machine.context.state.code_hash(address));
+
+    machine.stack.push(code_hash.to_u256());
+
     ControlFlow::Continue(1)
 }
 
-fn returndatasize(machine: &mut Machine) -> ControlFlow {
+fn returndatasize<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine.stack.push(machine.return_data_buffer.len().into());
 
     ControlFlow::Continue(1)
 }
 
-fn returndatacopy(machine: &mut Machine) -> ControlFlow {
-    let dest_offset = machine.stack.pop().unwrap().as_usize();
-    let offset = machine.stack.pop().unwrap().as_usize();
-    let size = machine.stack.pop().unwrap().as_usize();
+fn returndatacopy<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let dest_offset = machine.stack.pop().unwrap();
+    let offset = machine.stack.pop().unwrap();
+    let size = machine.stack.pop().unwrap();
 
-    let return_data = U256::from_big_endian(&machine.return_data_buffer[offset..]);
+    // EIP-211: reading past the end of the return data is an exceptional halt
+    let end = offset.overflowing_add(size);
+    if end.1 || end.0 > machine.return_data_buffer.len().into() {
+        return exit_error(EvmError::ReturnDataOutOfBounds);
+    }
 
-    machine.memory.set(dest_offset, return_data, size);
+    // copying nothing doesn't expand memory, whatever the destination
+    if size.is_zero() {
+        return ControlFlow::Continue(1);
+    }
+
+    let return_data = &machine.return_data_buffer[offset.as_usize()..end.0.as_usize()];
+
+    machine.memory.set_bytes(dest_offset.as_usize(), return_data);
 
     ControlFlow::Continue(1)
 }
 
-fn coinbase(machine: &mut Machine) -> ControlFlow {
+fn coinbase<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
-        .push(U256::from_big_endian(machine.block.coinbase));
+        .push(machine.block.coinbase.to_u256());
 
     ControlFlow::Continue(1)
 }
 
-fn timestamp(machine: &mut Machine) -> ControlFlow {
+fn timestamp<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
-        .push(U256::from_big_endian(machine.block.timestamp));
+        .push(machine.block.timestamp);
 
     ControlFlow::Continue(1)
 }
 
-fn number(machine: &mut Machine) -> ControlFlow {
+fn number<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
-        .push(U256::from_big_endian(machine.block.number));
+        .push(machine.block.number);
 
     ControlFlow::Continue(1)
 }
 
-fn difficulty(machine: &mut Machine) -> ControlFlow {
-    machine
-        .stack
-        .push(U256::from_big_endian(machine.block.difficulty));
+// EIP-4399: renamed PREVRANDAO at the Merge, from which it returns the beacon chain's randomness
+fn difficulty<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let value = if machine.block.fork.is_enabled(Fork::Paris) {
+        machine.block.prevrandao.to_u256()
+    } else {
+        machine.block.difficulty
+    };
+    machine.stack.push(value);
 
     ControlFlow::Continue(1)
 }
 
-fn gaslimit(machine: &mut Machine) -> ControlFlow {
+fn gaslimit<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
-        .push(U256::from_big_endian(machine.block.gaslimit));
+        .push(machine.block.gaslimit);
 
     ControlFlow::Continue(1)
 }
 
-fn chainid(machine: &mut Machine) -> ControlFlow {
+fn chainid<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
-        .push(U256::from_big_endian(machine.block.chainid));
+        .push(machine.block.chainid);
 
     ControlFlow::Continue(1)
 }
 
-fn selfbalance(machine: &mut Machine) -> ControlFlow {
+fn selfbalance<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let address = machine.context.address;
-    let balance = machine
-        .context
-        .state
-        .get_account_balance(address);
+    let balance = try_database!(machine.context.state.balance(address));
 
     machine.stack.push(balance);
 
     ControlFlow::Continue(1)
 }
 
-fn basefee(machine: &mut Machine) -> ControlFlow {
+fn basefee<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine
         .stack
-        .push(U256::from_big_endian(machine.block.basefee));
+        .push(machine.block.basefee);
 
     ControlFlow::Continue(1)
 }
 
-fn eval_pop(machine: &mut Machine) -> ControlFlow {
+// EIP-4844
+fn blobhash<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    if !machine.block.fork.is_enabled(Fork::Cancun) {
+        return exit_error(EvmError::InvalidInstruction);
+    }
+    let index = machine.stack.pop().unwrap();
+
+    machine.stack.push(machine.context.blob_hash(index));
+
+    ControlFlow::Continue(1)
+}
+
+// EIP-7516
+fn blobbasefee<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    if !machine.block.fork.is_enabled(Fork::Cancun) {
+        return exit_error(EvmError::InvalidInstruction);
+    }
+    machine.stack.push(machine.block.blob_basefee());
+
+    ControlFlow::Continue(1)
+}
+
+fn eval_pop<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine.stack.pop();
 
     ControlFlow::Continue(1)
 }
 
-fn mload(machine: &mut Machine) -> ControlFlow {
+fn mload<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let byte_offset = machine.stack.pop().unwrap();
 
     let res = machine.memory.get(byte_offset.as_usize(), WORD_BYTES);
@@ -785,7 +874,7 @@ fn mload(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn mstore(machine: &mut Machine) -> ControlFlow {
+fn mstore<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let byte_offset = machine.stack.pop().unwrap();
     let value = machine.stack.pop().unwrap();
 
@@ -796,7 +885,7 @@ fn mstore(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn mstore8(machine: &mut Machine) -> ControlFlow {
+fn mstore8<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let byte_offset = machine.stack.pop().unwrap();
     let value = machine.stack.pop().unwrap();
 
@@ -805,29 +894,34 @@ fn mstore8(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn sload(machine: &mut Machine) -> ControlFlow {
+fn sload<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let key = machine.stack.pop().unwrap();
 
-    let res = machine.storage.get(&key);
-
-    match res {
-        Some(result) => machine.stack.push(*result),
-        None => machine.stack.push(0.into()),
+    if let Err(err) = access_storage_key(machine, key, COLD_SLOAD_COST - WARM_STORAGE_READ_COST) {
+        return exit_error(err);
     }
 
+    let res = try_database!(machine.context.state.storage(machine.context.address, key));
+
+    machine.stack.push(res);
+
     ControlFlow::Continue(1)
 }
 
-fn sstore(machine: &mut Machine) -> ControlFlow {
+fn sstore<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let key = machine.stack.pop().unwrap();
     let value = machine.stack.pop().unwrap();
 
-    machine.storage.insert(key, value);
+    if let Err(err) = access_storage_key(machine, key, COLD_SLOAD_COST) {
+        return exit_error(err);
+    }
+
+    try_database!(machine.context.state.set_storage(machine.context.address, key, value));
 
     ControlFlow::Continue(1)
 }
 
-fn jump(machine: &mut Machine) -> ControlFlow {
+fn jump<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let a = machine.stack.pop().unwrap();
     let is_valid = machine.jump_map.is_valid(a);
 
@@ -838,7 +932,7 @@ fn jump(machine: &mut Machine) -> ControlFlow {
     }
 }
 
-fn jumpi(machine: &mut Machine) -> ControlFlow {
+fn jumpi<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let jump_to = machine.stack.pop().unwrap();
     let should_jump = machine.stack.pop().unwrap();
 
@@ -855,30 +949,40 @@ fn jumpi(machine: &mut Machine) -> ControlFlow {
     }
 }
 
-fn pc(machine: &mut Machine) -> ControlFlow {
+fn pc<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     machine.stack.push(machine.pc.into());
 
     ControlFlow::Continue(1)
 }
 
-fn msize(machine: &mut Machine) -> ControlFlow {
+fn msize<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let res = machine.memory.size();
     machine.stack.push(res.into());
 
     ControlFlow::Continue(1)
 }
 
-fn gas(machine: &mut Machine) -> ControlFlow {
+fn gas<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     // TODO: update to calculate gas properly (update tests first)
     machine.stack.push(U256::MAX);
 
     ControlFlow::Continue(1)
 }
-fn jumpdest(_machine: &mut Machine) -> ControlFlow {
+fn jumpdest<D: Database>(_machine: &mut Machine<D>) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn eval_push(machine: &mut Machine) -> ControlFlow {
+// EIP-3855
+fn push0<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    if !machine.block.fork.is_enabled(Fork::Shanghai) {
+        return exit_error(EvmError::InvalidInstruction);
+    }
+    machine.stack.push(U256::zero());
+
+    ControlFlow::Continue(1)
+}
+
+fn eval_push<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let n = usize::from(machine.opcode() - (Opcode::PUSH1 - 1));
     let start = machine.pc + 1;
     let end = start + n;
@@ -889,7 +993,7 @@ fn eval_push(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(n + 1)
 }
 
-fn dup(machine: &mut Machine) -> ControlFlow {
+fn dup<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let n = usize::from(machine.opcode() - Opcode::DUP1);
 
     let a = machine.stack.peek(n);
@@ -902,7 +1006,7 @@ fn dup(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn swap(machine: &mut Machine) -> ControlFlow {
+fn swap<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let n = usize::from(machine.opcode() - (Opcode::SWAP1 - 1));
 
     let a = match machine.stack.peek(0) {
@@ -926,7 +1030,7 @@ fn swap(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-fn log(machine: &mut Machine) -> ControlFlow {
+fn log<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let n = usize::from(machine.opcode() - Opcode::LOG0);
 
     let offset = machine.stack.pop().unwrap().as_usize();
@@ -946,68 +1050,140 @@ fn log(machine: &mut Machine) -> ControlFlow {
     ControlFlow::Continue(1)
 }
 
-// TODO: sender_nonce => starts as 0 but increments each CREATE: todo: increment & test incrementation of this value
-fn create(machine: &mut Machine) -> ControlFlow {
+fn create<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let value = machine.stack.pop().unwrap();
     let offset = machine.stack.pop().unwrap().as_usize();
     let size = machine.stack.pop().unwrap().as_usize();
 
-    let initialisation_code = machine.memory.get(offset, size);
+    let initialisation_code = machine.memory.get(offset, size).to_vec();
 
-    let address = create_address(machine.context.address, 0.into());
+    let nonce = try_database!(machine.context.state.nonce(machine.context.address));
+    let address = create_address(machine.context.address, nonce.into());
 
-    let mut value_bytes: [u8; 32] = [0; 32];
-    U256::to_big_endian(&value, &mut value_bytes);
+    create_contract(machine, address, value, initialisation_code)
+}
+
+fn create2<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let value = machine.stack.pop().unwrap();
+    let offset = machine.stack.pop().unwrap().as_usize();
+    let size = machine.stack.pop().unwrap().as_usize();
+    let salt = machine.stack.pop().unwrap();
+
+    let initialisation_code = machine.memory.get(offset, size).to_vec();
+
+    let address = create2_address(machine.context.address, salt, &initialisation_code);
+
+    create_contract(machine, address, value, initialisation_code)
+}
+
+fn create_contract<D: Database>(
+    machine: &mut Machine<D>,
+    address: H160,
+    value: U256,
+    initialisation_code: Vec<u8>,
+) -> ControlFlow {
+    // EIP-3860
+    let limit_initcode = machine.block.fork.is_enabled(Fork::Shanghai);
+    if limit_initcode && initialisation_code.len() > MAX_INITCODE_SIZE {
+        return exit_error(EvmError::InitcodeSizeExceeded);
+    }
+
+    machine.return_data_buffer = Vec::new();
+
+    machine.context.access_set.access_address(address);
+
+    if limit_initcode {
+        let initcode_cost = INITCODE_WORD_COST * ceil_divide(initialisation_code.len(), WORD_BYTES) as u64;
+        if let Err(err) = machine.gas.record_cost(initcode_cost) {
+            return exit_error(err);
+        }
+    }
+
+    // a creator which can't afford the value, or whose nonce can't be incremented (EIP-2681), fails the
+    // creation without running anything
+    let creator = machine.context.address;
+    let nonce = try_database!(machine.context.state.nonce(creator));
+    if nonce == u64::MAX || try_database!(machine.context.state.balance(creator)) < value {
+        machine.stack.push(0.into());
+        return ControlFlow::Continue(1);
+    }
+
+    // otherwise the creator's nonce is incremented even if the creation fails
+    try_database!(machine.context.state.set_nonce(creator, nonce + 1));
+
+    // EIP-684: creating a contract where one already is fails, consuming the gas it would have had
+    if try_database!(has_contract(&*machine.context.state, address)) {
+        machine.gas.record_cost(all_but_one_64th(machine.gas.remaining())).unwrap();
+        machine.stack.push(0.into());
+        return ControlFlow::Continue(1);
+    }
+
+    let call_data = String::new();
+    let gas_limit = all_but_one_64th(machine.gas.remaining());
+    let checkpoint = machine.context.state.checkpoint();
+
+    // EIP-161: contracts start with a nonce of 1, set before the initialisation code runs so that the
+    // contracts it creates are numbered from 1
+    let endowed = machine
+        .context
+        .state
+        .set_nonce(address, 1)
+        .and_then(|_| transfer(machine.context.state, creator, address, value));
+    if let Err(err) = endowed {
+        machine.context.state.revert(checkpoint);
+        return exit_error(database_error(err));
+    }
+
+    let context = machine.context.child(
+        address,
+        creator,
+        value,
+        &call_data,
+        false,
+        gas_limit,
+    );
 
     let res = evm(
-        initialisation_code,
-        Context::new(
-            address,
-            machine.context.address,
-            machine.context.origin,
-            machine.context.gasprice,
-            // QUESTION??? should value be 0 at this point? And then just sent after?
-            U256::from_big_endian(&value_bytes),
-            &String::new(),
-            machine.context.state.clone(),
-            false,
-        ),
-        machine.block,
-        None,
+        &initialisation_code,
+        context,
+        machine.block.clone(),
     );
 
+    if let Some(EvmError::Database(err)) = res.error {
+        machine.context.state.revert(checkpoint);
+        return exit_error(EvmError::Database(err));
+    }
+
     if !res.success {
+        machine.context.state.revert(checkpoint);
+        // only a reverted initialisation exposes its output as return data
+        if let Some(revert_data) = res.return_val {
+            machine.return_data_buffer = revert_data;
+        }
+        machine.gas.record_cost(res.gas_used).unwrap();
         machine.stack.push(0.into());
         return ControlFlow::Continue(1);
     }
 
-    machine.context.state = res.state;
-
     // code = return value of initialisation code
-    match &res.return_val {
-        // TODO: deal with code over 32_bytes long => update return val type to Vec<u8>
-        Some(code) => {
-            // TODO: what if code is more than 32 bytes????
-            let mut code_bytes: [u8; 32] = [0; 32];
-            U256::to_big_endian(code, &mut code_bytes);
-
-            // This could cut off an (unlikely) initial stop opcode. Update return_val to be a Vec<u8>
-            let code_vec = code.to_vec_u8_without_padding();
-
-            machine
-                .context
-                .state
-                .add_or_update_account(address, value, code_vec);
+    let code = res.return_val.unwrap_or_default();
+
+    match check_deployed_code(&code, gas_limit - res.gas_used, machine.block.fork) {
+        Ok(deposit_cost) => {
+            machine.gas.record_cost(res.gas_used + deposit_cost).unwrap();
         }
-        None => {
-            machine
-                .context
-                .state
-                .add_or_update_account(address, value, Vec::new());
+        Err(_) => {
+            // the sub context halted exceptionally, so all of its gas is consumed
+            machine.context.state.revert(checkpoint);
+            machine.gas.record_cost(gas_limit).unwrap();
+            machine.stack.push(0.into());
+            return ControlFlow::Continue(1);
         }
     }
 
-    // UPDATE STATE
+    machine.context.access_set = res.access_set;
+    try_database!(machine.context.state.set_code(address, code));
+    machine.context.state.commit(checkpoint);
 
     machine.stack.push(address.to_u256());
 
@@ -1025,9 +1201,8 @@ fn create(machine: &mut Machine) -> ControlFlow {
 //     ControlFlow::Continue(1)
 // }
 
-fn call(machine: &mut Machine) -> ControlFlow {
-    // TODO: handle gas
-    let _gas = machine.stack.pop().unwrap();
+fn call<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let gas = machine.stack.pop().unwrap();
     let address = machine.stack.pop().unwrap().to_h160();
     let value = machine.stack.pop().unwrap();
     let args_offset = machine.stack.pop().unwrap().as_usize();
@@ -1035,197 +1210,970 @@ fn call(machine: &mut Machine) -> ControlFlow {
     let ret_offset = machine.stack.pop().unwrap().as_usize();
     let ret_size = machine.stack.pop().unwrap().as_usize();
 
-    let data = machine.memory.get(args_offset, args_size);
+    if machine.context.is_static && !value.is_zero() {
+        return exit_error(EvmError::OpcodeNotStatic(Opcode::CALL));
+    }
 
-    let code = machine.context.state.get_account_code(address);
+    let data = machine.memory.get(args_offset, args_size).to_vec();
 
+    if let Err(err) = access_account(machine, address) {
+        return exit_error(err);
+    }
+
+    let code = match call_code(machine, address) {
+        Ok(code) => code,
+        Err(err) => return exit_error(err),
+    };
+
+    let gas_limit = call_gas_limit(machine, gas);
+
+    // a caller which can't afford the value fails the call without running the callee
+    if try_database!(machine.context.state.balance(machine.context.address)) < value {
+        set_return_data(machine, None, ret_offset, ret_size);
+        machine.stack.push(0.into());
+        return ControlFlow::Continue(1);
+    }
+
+    if let Some(precompile) = machine.context.native_precompiles.get(address) {
+        let precompile = precompile.as_ref();
+        return call_native_precompile(
+            machine, precompile, &data, gas_limit, address, value, ret_offset, ret_size,
+        );
+    }
+
+    if let Some(precompile) = get_precompile(address, machine.block.fork) {
+        return call_precompile(
+            machine, precompile, &data, gas_limit, address, value, ret_offset, ret_size,
+        );
+    }
 
     // TODO: use trait for this
     let mut value_bytes: [u8; 32] = [0; 32];
     U256::to_big_endian(&value, &mut value_bytes);
 
-    let data_string = hex::encode(data);
+    let data_string = hex::encode(&data);
+
+    // the value is moved after the checkpoint, so it is only kept if the call succeeds
+    let checkpoint = machine.context.state.checkpoint();
+    let caller = machine.context.address;
+    try_database!(transfer(&mut *machine.context.state, caller, address, value));
+
+    let is_static = machine.context.is_static;
+    let context = machine.context.child(
+        address,
+        caller,
+        U256::from_big_endian(&value_bytes),
+        &data_string,
+        is_static,
+        gas_limit,
+    );
 
     let res = evm(
         code,
-        Context::new(
-            address,
-            machine.context.address,
-            machine.context.origin,
-            machine.context.gasprice,
-            U256::from_big_endian(&value_bytes),
-            &data_string,
-            machine.context.state.clone(),
-            false,
-        ),
-        machine.block,
-        None,
+        context,
+        machine.block.clone(),
     );
 
-    match &res.return_val {
-        Some(value) => {
-            let return_val_without_padding = value.to_vec_u8_without_padding();
+    finish_call(machine, res, checkpoint, ret_offset, ret_size)
+}
 
-            machine.memory.set(ret_offset, *value, ret_size);
-            machine.return_data_buffer = return_val_without_padding;
-        }
-        None => {
-            machine.return_data_buffer = Vec::new();
-        }
-    };
+// Keeps the changes of a call which succeeded and undoes those of one which didn't
+fn finish_call<D: Database>(
+    machine: &mut Machine<D>,
+    res: EvmResult,
+    checkpoint: usize,
+    ret_offset: usize,
+    ret_size: usize,
+) -> ControlFlow {
+    if let Some(EvmError::Database(err)) = res.error {
+        machine.context.state.revert(checkpoint);
+        return exit_error(EvmError::Database(err));
+    }
+
+    machine.gas.record_cost(res.gas_used).unwrap();
+    set_return_data(machine, res.return_val, ret_offset, ret_size);
 
     if res.success {
-        machine.context.state = res.state;
+        machine.context.state.commit(checkpoint);
+        machine.context.access_set = res.access_set;
         machine.stack.push(1.into());
     } else {
+        machine.context.state.revert(checkpoint);
         machine.stack.push(0.into());
     }
 
     ControlFlow::Continue(1)
 }
 
-fn eval_return(machine: &mut Machine) -> ControlFlow {
-    let offset = machine.stack.pop().unwrap().as_usize();
-    let size = machine.stack.pop().unwrap().as_usize();
-
-    let res = machine.memory.get(offset, size);
+// EIP-2929: the first access to an account in a transaction is more expensive, precompiles are always warm.
+// Returns the whole warm or cold cost of the access, zero when accesses aren't charged
+fn account_access_cost<D: Database>(machine: &mut Machine<D>, address: H160) -> u64 {
+    let warm = machine.context.access_set.access_address(address)
+        || get_precompile(address, machine.block.fork).is_some();
 
-    exit_success(ExitSuccess::Return(U256::from_big_endian(res)))
+    if !machine.context.access_set.charge_cold_access || !machine.block.fork.is_enabled(Fork::Berlin) {
+        0
+    } else if warm {
+        WARM_STORAGE_READ_COST
+    } else {
+        COLD_ACCOUNT_ACCESS_COST
+    }
 }
 
-fn delegatecall(machine: &mut Machine) -> ControlFlow {
-    // TODO: handle gas
-    let _gas = machine.stack.pop().unwrap();
-    let address = machine.stack.pop().unwrap().to_h160();
-    let args_offset = machine.stack.pop().unwrap().as_usize();
-    let args_size = machine.stack.pop().unwrap().as_usize();
-    let ret_offset = machine.stack.pop().unwrap().as_usize();
-    let ret_size = machine.stack.pop().unwrap().as_usize();
-
-    let data = machine.memory.get(args_offset, args_size);
+// Accessing the account an opcode operates on. Only the extra cost of a cold access is charged, the warm
+// cost is part of the opcode's base cost which is not metered yet
+fn access_account<D: Database>(machine: &mut Machine<D>, address: H160) -> Result<(), EvmError> {
+    let cost = account_access_cost(machine, address);
+    machine.gas.record_cost(cost.saturating_sub(WARM_STORAGE_READ_COST))
+}
 
-    let code = machine.context.state.get_account_code(address);
+// EIP-2929: as above, for slots of the executing account's storage. SLOAD only pays the extra cost over a
+// warm read, but SSTORE pays the whole cold cost on top of its own
+fn access_storage_key<D: Database>(
+    machine: &mut Machine<D>,
+    key: U256,
+    cold_cost: u64,
+) -> Result<(), EvmError> {
+    let warm = machine
+        .context
+        .access_set
+        .access_storage_key(machine.context.address, key);
+
+    if warm
+        || !machine.context.access_set.charge_cold_access
+        || !machine.block.fork.is_enabled(Fork::Berlin)
+    {
+        return Ok(());
+    }
+    machine.gas.record_cost(cold_cost)
+}
+
+// EIP-7702: calling an account which delegated runs its delegate's code, only one level deep as the
+// delegate's own designator isn't followed. Accessing the delegate isn't part of the opcode's base
+// cost, so unlike access_account its whole warm or cold cost is charged. EXTCODE* are left reading the
+// designator itself, as in the final version of the EIP
+fn call_code<D: Database>(machine: &mut Machine<D>, address: H160) -> Result<Vec<u8>, EvmError> {
+    let code = machine.context.state.code(address).map_err(database_error)?;
+    let delegate = match delegated_address(&code) {
+        Some(delegate) if machine.block.fork.is_enabled(Fork::Prague) => delegate,
+        _ => return Ok(code),
+    };
 
+    let cost = account_access_cost(machine, delegate);
+    machine.gas.record_cost(cost)?;
 
-    let data_string = hex::encode(data);
+    machine.context.state.code(delegate).map_err(database_error)
+}
 
-    let res = evm(
-        code,
-        Context::new(
-            machine.context.address,
-            machine.context.caller,
-            machine.context.origin,
-            machine.context.gasprice,
-            machine.context.value,
-            &data_string,
-            machine.context.state.clone(),
-            false,
-        ),
-        machine.block,
-        Some(&mut machine.storage),
-    );
+// EIP-150: the sub context gets at most all but one 64th of the remaining gas
+fn call_gas_limit<D: Database>(machine: &Machine<D>, gas: U256) -> u64 {
+    let available = all_but_one_64th(machine.gas.remaining());
+    if gas > available.into() {
+        available
+    } else {
+        gas.as_u64()
+    }
+}
 
-    match &res.return_val {
+fn set_return_data<D: Database>(
+    machine: &mut Machine<D>,
+    return_data: Option<Vec<u8>>,
+    ret_offset: usize,
+    ret_size: usize,
+) {
+    match return_data {
         Some(value) => {
-            let return_val_without_padding = value.to_vec_u8_without_padding();
-
-            machine.memory.set(ret_offset, *value, ret_size);
-            machine.return_data_buffer = return_val_without_padding;
+            // any bytes beyond ret_size are only available via RETURNDATACOPY
+            let copy_size = ret_size.min(value.len());
+            machine.memory.get(ret_offset, ret_size);
+            machine.memory.set_bytes(ret_offset, &value[..copy_size]);
+            machine.return_data_buffer = value;
         }
         None => {
             machine.return_data_buffer = Vec::new();
         }
     };
+}
 
-    if res.success {
-        machine.context.state = res.state;
-        machine.stack.push(1.into());
-    } else {
+// Moves the value of a call from the caller to the callee
+fn transfer<D: Database>(state: &mut D, from: H160, to: H160, value: U256) -> Result<(), D::Error> {
+    if !value.is_zero() {
+        state.decrement_balance(from, value)?;
+        state.increment_balance(to, value)?;
+    }
+    Ok(())
+}
+
+fn database_error(err: impl Debug) -> EvmError {
+    EvmError::Database(format!("{:?}", err))
+}
+
+// Precompiles receive the value of a call like any other account, but only when they succeed
+#[allow(clippy::too_many_arguments)]
+fn call_precompile<D: Database>(
+    machine: &mut Machine<D>,
+    precompile: Precompile,
+    input: &[u8],
+    gas_limit: u64,
+    address: H160,
+    value: U256,
+    ret_offset: usize,
+    ret_size: usize,
+) -> ControlFlow {
+    match precompile(input, gas_limit) {
+        Ok(res) => {
+            machine.gas.record_cost(res.gas_used).unwrap();
+            let caller = machine.context.address;
+            try_database!(transfer(&mut *machine.context.state, caller, address, value));
+            set_return_data(machine, Some(res.output), ret_offset, ret_size);
+            machine.stack.push(1.into());
+        }
+        Err(_) => {
+            machine.gas.record_cost(gas_limit).unwrap();
+            set_return_data(machine, None, ret_offset, ret_size);
+            machine.stack.push(0.into());
+        }
+    }
+
+    ControlFlow::Continue(1)
+}
+
+// User registered precompiles are checked before the standard ones, so they can also replace them
+#[allow(clippy::too_many_arguments)]
+fn call_native_precompile<D: Database>(
+    machine: &mut Machine<D>,
+    precompile: &dyn NativePrecompile,
+    input: &[u8],
+    gas_limit: u64,
+    address: H160,
+    value: U256,
+    ret_offset: usize,
+    ret_size: usize,
+) -> ControlFlow {
+    let res = precompile.call(input, gas_limit);
+
+    if res.gas_used > gas_limit {
+        machine.gas.record_cost(gas_limit).unwrap();
+        set_return_data(machine, None, ret_offset, ret_size);
         machine.stack.push(0.into());
+        return ControlFlow::Continue(1);
+    }
+
+    machine.gas.record_cost(res.gas_used).unwrap();
+    if res.success {
+        let caller = machine.context.address;
+        try_database!(transfer(&mut *machine.context.state, caller, address, value));
     }
+    set_return_data(machine, Some(res.output), ret_offset, ret_size);
+    machine.stack.push(if res.success { 1 } else { 0 }.into());
 
     ControlFlow::Continue(1)
 }
 
-// TODO: merge call opcode shared logic into a single call function with a type enum passed in
-fn staticcall(machine: &mut Machine) -> ControlFlow {
-    // TODO: handle gas
-    let _gas = machine.stack.pop().unwrap();
+fn eval_return<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let offset = machine.stack.pop().unwrap().as_usize();
+    let size = machine.stack.pop().unwrap().as_usize();
+
+    let res = machine.memory.get(offset, size);
+
+    exit_success(ExitSuccess::Return(res.to_vec()))
+}
+
+fn delegatecall<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let gas = machine.stack.pop().unwrap();
     let address = machine.stack.pop().unwrap().to_h160();
     let args_offset = machine.stack.pop().unwrap().as_usize();
     let args_size = machine.stack.pop().unwrap().as_usize();
     let ret_offset = machine.stack.pop().unwrap().as_usize();
     let ret_size = machine.stack.pop().unwrap().as_usize();
 
-    let data = machine.memory.get(args_offset, args_size);
+    let data = machine.memory.get(args_offset, args_size).to_vec();
+
+    if let Err(err) = access_account(machine, address) {
+        return exit_error(err);
+    }
+
+    let code = match call_code(machine, address) {
+        Ok(code) => code,
+        Err(err) => return exit_error(err),
+    };
+
+    let gas_limit = call_gas_limit(machine, gas);
+
+    if let Some(precompile) = machine.context.native_precompiles.get(address) {
+        let precompile = precompile.as_ref();
+        return call_native_precompile(
+            machine, precompile, &data, gas_limit, address, 0.into(), ret_offset, ret_size,
+        );
+    }
 
-    let code = machine.context.state.get_account_code(address);
+    if let Some(precompile) = get_precompile(address, machine.block.fork) {
+        return call_precompile(
+            machine, precompile, &data, gas_limit, address, 0.into(), ret_offset, ret_size,
+        );
+    }
 
-    let data_string = hex::encode(data);
+    let data_string = hex::encode(&data);
+
+    let checkpoint = machine.context.state.checkpoint();
+    let context = &mut machine.context;
+    let (address, caller, value) = (context.address, context.caller, context.value);
+    let is_static = context.is_static;
+    let context = context.child(
+        address,
+        caller,
+        value,
+        &data_string,
+        is_static,
+        gas_limit,
+    );
 
     let res = evm(
         code,
-        Context::new(
-            address,
-            machine.context.address,
-            machine.context.origin,
-            machine.context.gasprice,
-            0.into(),
-            &data_string,
-            machine.context.state.clone(),
-            true,
-        ),
-        machine.block,
-        None,
+        context,
+        machine.block.clone(),
     );
 
-    match &res.return_val {
-        Some(value) => {
-            let return_val_without_padding = value.to_vec_u8_without_padding();
+    finish_call(machine, res, checkpoint, ret_offset, ret_size)
+}
 
-            machine.memory.set(ret_offset, *value, ret_size);
-            machine.return_data_buffer = return_val_without_padding;
-        }
-        None => {
-            machine.return_data_buffer = Vec::new();
-        }
+// TODO: merge call opcode shared logic into a single call function with a type enum passed in
+fn staticcall<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
+    let gas = machine.stack.pop().unwrap();
+    let address = machine.stack.pop().unwrap().to_h160();
+    let args_offset = machine.stack.pop().unwrap().as_usize();
+    let args_size = machine.stack.pop().unwrap().as_usize();
+    let ret_offset = machine.stack.pop().unwrap().as_usize();
+    let ret_size = machine.stack.pop().unwrap().as_usize();
+
+    let data = machine.memory.get(args_offset, args_size).to_vec();
+
+    if let Err(err) = access_account(machine, address) {
+        return exit_error(err);
+    }
+
+    let code = match call_code(machine, address) {
+        Ok(code) => code,
+        Err(err) => return exit_error(err),
     };
 
-    if res.success {
-        machine.context.state = res.state;
-        machine.stack.push(1.into());
-    } else {
-        machine.stack.push(0.into());
+    let gas_limit = call_gas_limit(machine, gas);
+
+    if let Some(precompile) = machine.context.native_precompiles.get(address) {
+        let precompile = precompile.as_ref();
+        return call_native_precompile(
+            machine, precompile, &data, gas_limit, address, 0.into(), ret_offset, ret_size,
+        );
     }
 
-    ControlFlow::Continue(1)
+    if let Some(precompile) = get_precompile(address, machine.block.fork) {
+        return call_precompile(
+            machine, precompile, &data, gas_limit, address, 0.into(), ret_offset, ret_size,
+        );
+    }
+
+    let data_string = hex::encode(&data);
+
+    let checkpoint = machine.context.state.checkpoint();
+    let caller = machine.context.address;
+    let context = machine.context.child(
+        address,
+        caller,
+        0.into(),
+        &data_string,
+        true,
+        gas_limit,
+    );
+
+    let res = evm(
+        code,
+        context,
+        machine.block.clone(),
+    );
+
+    finish_call(machine, res, checkpoint, ret_offset, ret_size)
 }
 
-fn revert(machine: &mut Machine) -> ControlFlow {
+fn revert<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let offset = machine.stack.pop().unwrap().as_usize();
     let size = machine.stack.pop().unwrap().as_usize();
 
     let res = machine.memory.get(offset, size);
 
-    exit_error(EvmError::Revert(U256::from_big_endian(res)))
+    exit_error(EvmError::Revert(res.to_vec()))
 }
 
-fn invalid(_machine: &mut Machine) -> ControlFlow {
+fn invalid<D: Database>(_machine: &mut Machine<D>) -> ControlFlow {
     exit_error(EvmError::InvalidInstruction)
 }
 
-fn selfdestruct(machine: &mut Machine) -> ControlFlow {
+fn selfdestruct<D: Database>(machine: &mut Machine<D>) -> ControlFlow {
     let address = machine.stack.pop().unwrap();
 
-    let balance = machine
-        .context
-        .state
-        .destruct_account(machine.context.address);
+    if let Err(err) = access_account(machine, address.to_h160()) {
+        return exit_error(err);
+    }
 
-    machine
-        .context
-        .state
-        .increment_balance(address.to_h160(), balance + machine.context.value);
+    let balance = try_database!(machine.context.state.destruct_account(machine.context.address));
+
+    try_database!(machine.context.state.increment_balance(address.to_h160(), balance));
 
     ControlFlow::Continue(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access_list::AccessSet;
+    use crate::authorization::delegation_designator;
+    use crate::block::Block;
+    use crate::context::Context;
+    use crate::machine::EvmResult;
+    use crate::precompiles::NativeOutput;
+    use crate::state::State;
+    use primitive_types::H256;
+
+    fn run(code: &str, state: &mut State, block: Block) -> EvmResult {
+        run_with(code, state, block, |_| {})
+    }
+
+    fn run_with(
+        code: &str,
+        state: &mut State,
+        block: Block,
+        f: impl FnOnce(&mut Context<State>),
+    ) -> EvmResult {
+        let call_data = String::new();
+        let mut context = Context::new(
+            H160::repeat_byte(0xaa),
+            H160::repeat_byte(0xbb),
+            H160::repeat_byte(0xbb),
+            U256::zero(),
+            U256::zero(),
+            &call_data,
+            state,
+            false,
+        );
+        f(&mut context);
+        evm(hex::decode(code).unwrap(), context, block)
+    }
+
+    // CALL to 0x1234 with a gas of 0xffff and the value, then RETURNDATASIZE and MLOAD(0)
+    fn call_0x1234(value: u8) -> String {
+        format!("602060006000600060{:02x}61123461fffff13d600051", value)
+    }
+
+    fn precompile_address() -> H160 {
+        H160::from_low_u64_be(0x1234)
+    }
+
+    fn run_with_precompile(
+        value: u8,
+        state: &mut State,
+        precompile: impl Fn(&[u8], u64) -> NativeOutput + 'static,
+    ) -> EvmResult {
+        run_with(&call_0x1234(value), state, Block::default(), |context| {
+            context.native_precompiles.register(precompile_address(), precompile)
+        })
+    }
+
+    #[test]
+    fn blockhash_returns_the_256_most_recent_hashes() {
+        let mut state = State::new();
+        for number in [743u64, 744, 999, 1000] {
+            state.set_block_hash(number.into(), H256::from_low_u64_be(number));
+        }
+        let block = Block {
+            number: 1000.into(),
+            ..Default::default()
+        };
+
+        // BLOCKHASH of 999, 744, 743 and the current block 1000
+        let res = run("6103e7406102e8406102e7406103e840", &mut state, block);
+
+        assert!(res.success);
+        assert_eq!(res.stack, vec![0.into(), 0.into(), 744.into(), 999.into()]);
+    }
+
+    #[test]
+    fn difficulty_returns_prevrandao_from_the_merge() {
+        let block = |fork| Block {
+            difficulty: 0x20000.into(),
+            prevrandao: H256::repeat_byte(0x42),
+            fork,
+            ..Default::default()
+        };
+
+        let res = run("44", &mut State::new(), block(Fork::Paris));
+        assert_eq!(res.stack, vec![H256::repeat_byte(0x42).to_u256()]);
+
+        let res = run("44", &mut State::new(), block(Fork::London));
+        assert_eq!(res.stack, vec![0x20000.into()]);
+    }
+
+    #[test]
+    fn blob_opcodes_are_only_defined_from_cancun() {
+        let block = |fork| Block {
+            fork,
+            ..Default::default()
+        };
+
+        // BLOBHASH(0) and BLOBBASEFEE
+        for code in ["600049", "4a"] {
+            assert!(run(code, &mut State::new(), block(Fork::Cancun)).success, "{}", code);
+            let res = run(code, &mut State::new(), block(Fork::Shanghai));
+            assert!(matches!(res.error, Some(EvmError::InvalidInstruction)), "{}", code);
+        }
+    }
+
+    #[test]
+    fn push0_is_only_defined_from_shanghai() {
+        let block = |fork| Block {
+            fork,
+            ..Default::default()
+        };
+
+        let res = run("60015f", &mut State::new(), block(Fork::Shanghai));
+        assert!(res.success);
+        assert_eq!(res.stack, vec![0.into(), 1.into()]);
+
+        let res = run("60015f", &mut State::new(), block(Fork::London));
+        assert!(matches!(res.error, Some(EvmError::InvalidInstruction)));
+    }
+
+    #[test]
+    fn create_uses_and_increments_the_creator_nonce() {
+        let creator = H160::repeat_byte(0xaa);
+        let mut state = State::new();
+        state.set_nonce(creator, 5).unwrap();
+
+        // CREATE with empty initialisation code
+        let res = run("600060006000f0", &mut state, Block::default());
+
+        assert!(res.success);
+        assert_eq!(res.stack, vec![create_address(creator, 5.into()).to_u256()]);
+        assert_eq!(state.nonce(creator).unwrap(), 6);
+    }
+
+    #[test]
+    fn create_increments_the_nonce_when_initialisation_fails() {
+        let creator = H160::repeat_byte(0xaa);
+
+        // CREATE with the initialisation code INVALID
+        let mut state = State::new();
+        let res = run("60fe600053600160006000f0", &mut state, Block::default());
+
+        assert!(res.success);
+        assert_eq!(res.stack, vec![0.into()]);
+        assert_eq!(state.nonce(creator).unwrap(), 1);
+    }
+
+    #[test]
+    fn created_contracts_start_with_a_nonce_of_one() {
+        let creator = H160::repeat_byte(0xaa);
+
+        let mut state = State::new();
+        let res = run("600060006000f0", &mut state, Block::default());
+
+        assert!(res.success);
+        assert_eq!(state.nonce(create_address(creator, 0.into())).unwrap(), 1);
+    }
+
+    #[test]
+    fn create_moves_the_value_from_the_creator() {
+        let creator = H160::repeat_byte(0xaa);
+        let mut state = State::new();
+        state.add_account(creator, 10.into(), Vec::new());
+
+        // CREATE with a value of 9 and empty initialisation code
+        let res = run("600060006009f0", &mut state, Block::default());
+
+        let created = create_address(creator, 0.into());
+        assert_eq!(res.stack, vec![created.to_u256()]);
+        assert_eq!(state.balance(creator).unwrap(), 1.into());
+        assert_eq!(state.balance(created).unwrap(), 9.into());
+    }
+
+    #[test]
+    fn create_fails_when_the_creator_cannot_pay_or_increment_its_nonce() {
+        let creator = H160::repeat_byte(0xaa);
+
+        let mut state = State::new();
+        state.add_account(creator, 5.into(), Vec::new());
+        let res = run("600060006009f0", &mut state, Block::default());
+        assert!(res.success);
+        assert_eq!(res.stack, vec![0.into()]);
+        assert_eq!(state.balance(creator).unwrap(), 5.into());
+        assert_eq!(state.nonce(creator).unwrap(), 0);
+        assert!(state.get_account(create_address(creator, 0.into())).is_none());
+
+        // EIP-2681
+        let mut state = State::new();
+        state.set_nonce(creator, u64::MAX).unwrap();
+        let res = run("600060006000f0", &mut state, Block::default());
+        assert!(res.success);
+        assert_eq!(res.stack, vec![0.into()]);
+        assert_eq!(state.nonce(creator).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn contracts_created_by_initialisation_code_are_numbered_from_one() {
+        let creator = H160::repeat_byte(0xaa);
+        // CREATE with the initialisation code CREATE(0, 0, 0)
+        let code = "67600060006000f000600052600860186000f0";
+
+        let mut state = State::new();
+        let res = run(code, &mut state, Block::default());
+
+        let outer = create_address(creator, 0.into());
+        assert_eq!(res.stack, vec![outer.to_u256()]);
+        assert_eq!(state.nonce(outer).unwrap(), 2);
+        assert_eq!(state.nonce(create_address(outer, 1.into())).unwrap(), 1);
+        assert!(state.get_account(create_address(outer, 0.into())).is_none());
+    }
+
+    #[test]
+    fn initcode_limit_applies_from_shanghai() {
+        let block = |fork| Block {
+            fork,
+            ..Default::default()
+        };
+        // CREATE with 49153 zero bytes of initialisation code
+        let code = "6200c00160006000f0";
+
+        let res = run(code, &mut State::new(), block(Fork::Shanghai));
+        assert!(matches!(res.error, Some(EvmError::InitcodeSizeExceeded)));
+
+        let res = run(code, &mut State::new(), block(Fork::London));
+        assert!(res.success);
+        assert_ne!(res.stack, vec![0.into()]);
+    }
+
+    #[test]
+    fn code_starting_with_0xef_is_rejected_from_london() {
+        let block = |fork| Block {
+            fork,
+            ..Default::default()
+        };
+        // CREATE with the initialisation code RETURN(MSTORE8(0, 0xef), 1)
+        let code = "6960ef60005360016000f3600052600a60166000f0";
+
+        let res = run(code, &mut State::new(), block(Fork::London));
+        assert!(res.success);
+        assert_eq!(res.stack, vec![0.into()]);
+
+        let res = run(code, &mut State::new(), block(Fork::Berlin));
+        assert!(res.success);
+        assert_ne!(res.stack, vec![0.into()]);
+    }
+
+    #[test]
+    fn native_precompiles_are_called() {
+        let mut state = State::new();
+        let res = run_with_precompile(0, &mut state, |input, gas_limit| {
+            assert!(input.is_empty());
+            assert_eq!(gas_limit, 0xffff);
+            NativeOutput {
+                output: vec![0x11; 32],
+                gas_used: 100,
+                success: true,
+            }
+        });
+
+        assert!(res.success);
+        assert_eq!(res.gas_used, 100);
+        assert_eq!(res.stack, vec![U256::from_big_endian(&[0x11; 32]), 32.into(), 1.into()]);
+    }
+
+    #[test]
+    fn failed_native_precompiles_return_their_output() {
+        let mut state = State::new();
+        let res = run_with_precompile(0, &mut state, |_, _| NativeOutput {
+            output: vec![0xee; 4],
+            gas_used: 50,
+            success: false,
+        });
+
+        assert!(res.success);
+        assert_eq!(res.gas_used, 50);
+        assert_eq!(res.stack, vec![U256::from(0xeeeeeeeeu32) << 224, 4.into(), 0.into()]);
+    }
+
+    #[test]
+    fn native_precompiles_out_of_gas_consume_the_call_gas() {
+        let mut state = State::new();
+        let res = run_with_precompile(0, &mut state, |_, _| NativeOutput {
+            output: vec![0x11; 32],
+            gas_used: 0x10000,
+            success: true,
+        });
+
+        assert!(res.success);
+        assert_eq!(res.gas_used, 0xffff);
+        assert_eq!(res.stack, vec![0.into(), 0.into(), 0.into()]);
+    }
+
+    #[test]
+    fn native_precompiles_receive_the_value() {
+        let mut state = State::new();
+        state.increment_balance(H160::repeat_byte(0xaa), 10.into()).unwrap();
+        let success = |_: &[u8], _| NativeOutput {
+            output: Vec::new(),
+            gas_used: 0,
+            success: true,
+        };
+
+        let mut succeeded = state.clone();
+        let res = run_with_precompile(3, &mut succeeded, success);
+
+        assert_eq!(res.stack[2], 1.into());
+        assert_eq!(succeeded.balance(H160::repeat_byte(0xaa)).unwrap(), 7.into());
+        assert_eq!(succeeded.balance(precompile_address()).unwrap(), 3.into());
+
+        let res = run_with_precompile(3, &mut state, |_, _| NativeOutput {
+            output: Vec::new(),
+            gas_used: 0,
+            success: false,
+        });
+
+        assert_eq!(res.stack[2], 0.into());
+        assert_eq!(state.balance(H160::repeat_byte(0xaa)).unwrap(), 10.into());
+        assert_eq!(state.balance(precompile_address()).unwrap(), 0.into());
+    }
+
+    #[test]
+    fn precompiles_receive_the_value() {
+        let mut state = State::new();
+        state.increment_balance(H160::repeat_byte(0xaa), 10.into()).unwrap();
+
+        // CALL the identity precompile at 0x04 with a value of 3
+        let res = run("60006000600060006003600461fffff1", &mut state, Block::default());
+
+        assert_eq!(res.stack, vec![1.into()]);
+        assert_eq!(state.balance(H160::repeat_byte(0xaa)).unwrap(), 7.into());
+        assert_eq!(state.balance(H160::from_low_u64_be(4)).unwrap(), 3.into());
+    }
+
+    #[test]
+    fn calls_transfer_the_value() {
+        let mut state = State::new();
+        state.increment_balance(H160::repeat_byte(0xaa), 10.into()).unwrap();
+
+        // CALL 0x1234, which has no code, with a value of 3
+        let res = run(&call_0x1234(3), &mut state, Block::default());
+
+        assert_eq!(res.stack[2], 1.into());
+        assert_eq!(state.balance(H160::repeat_byte(0xaa)).unwrap(), 7.into());
+        assert_eq!(state.balance(precompile_address()).unwrap(), 3.into());
+    }
+
+    #[test]
+    fn calls_without_enough_balance_fail() {
+        let mut state = State::new();
+        state.increment_balance(H160::repeat_byte(0xaa), 2.into()).unwrap();
+
+        let res = run(&call_0x1234(3), &mut state, Block::default());
+
+        assert_eq!(res.stack, vec![0.into(), 0.into(), 0.into()]);
+        assert_eq!(state.balance(H160::repeat_byte(0xaa)).unwrap(), 2.into());
+        assert_eq!(state.balance(precompile_address()).unwrap(), 0.into());
+    }
+
+    #[test]
+    fn selfdestruct_moves_the_value_of_the_call_once() {
+        let contract = H160::from_low_u64_be(0x1234);
+        let beneficiary = H160::from_low_u64_be(0x5678);
+        let mut state = State::new();
+        state.increment_balance(H160::repeat_byte(0xaa), 10.into()).unwrap();
+        // SELFDESTRUCT to 0x5678
+        state.set_code(contract, hex::decode("615678ff").unwrap()).unwrap();
+
+        let res = run(&call_0x1234(3), &mut state, Block::default());
+
+        assert_eq!(res.stack[2], 1.into());
+        assert_eq!(state.balance(H160::repeat_byte(0xaa)).unwrap(), 7.into());
+        assert_eq!(state.balance(beneficiary).unwrap(), 3.into());
+    }
+
+    #[test]
+    fn failed_calls_undo_their_changes() {
+        let mut state = State::new();
+        // SSTORE(0, 1), then REVERT
+        let code = hex::decode("600160005560006000fd").unwrap();
+        state.set_code(H160::from_low_u64_be(0x1234), code).unwrap();
+
+        let res = run(&call_0x1234(0), &mut state, Block::default());
+
+        assert_eq!(res.stack[2], 0.into());
+        assert_eq!(state.storage(H160::from_low_u64_be(0x1234), 0.into()).unwrap(), 0.into());
+    }
+
+    #[test]
+    fn database_errors_fail_every_frame() {
+        let beneficiary = H160::from_low_u64_be(0x5678);
+        let mut state = State::new();
+        state.add_account(beneficiary, U256::MAX, Vec::new());
+        state.add_account(H160::from_low_u64_be(0x1234), 1.into(), hex::decode("615678ff").unwrap());
+
+        // the callee's balance overflows its beneficiary's when it self destructs
+        let res = run(&call_0x1234(0), &mut state, Block::default());
+
+        assert!(!res.success);
+        assert!(matches!(res.error, Some(EvmError::Database(_))));
+    }
+
+    #[test]
+    fn create_collision_fails_the_creation() {
+        let creator = H160::repeat_byte(0xaa);
+        let mut state = State::new();
+        state.set_code(create_address(creator, 0.into()), vec![0x00]).unwrap();
+
+        let res = run("600060006000f0", &mut state, Block::default());
+
+        assert!(res.success);
+        assert_eq!(res.stack, vec![0.into()]);
+        assert_eq!(state.nonce(creator).unwrap(), 1);
+        assert_eq!(state.code(create_address(creator, 0.into())).unwrap(), vec![0x00]);
+    }
+
+    // Runs the code with cold accesses charged, after the closure has warmed up the access set
+    fn run_charged(code: &str, fork: Fork, warm: impl FnOnce(&mut AccessSet)) -> EvmResult {
+        let block = Block {
+            fork,
+            ..Default::default()
+        };
+        run_with(code, &mut State::new(), block, |context| {
+            context.access_set.charge_cold_access = true;
+            warm(&mut context.access_set);
+        })
+    }
+
+    fn warm_slot(access_set: &mut AccessSet) {
+        access_set.access_storage_key(H160::repeat_byte(0xaa), 0.into());
+    }
+
+    fn warm_account(access_set: &mut AccessSet) {
+        access_set.access_address(H160::from_low_u64_be(0x1234));
+    }
+
+    #[test]
+    fn sload_charges_cold_slots_once() {
+        // SLOAD(0), twice
+        let code = "60005450600054";
+        let cold = run_charged(code, Fork::Prague, |_| {}).gas_used;
+        let warm = run_charged(code, Fork::Prague, warm_slot).gas_used;
+
+        assert_eq!(cold - warm, 2100 - 100);
+    }
+
+    #[test]
+    fn sstore_charges_the_whole_cold_cost() {
+        // SSTORE(0, 1)
+        let code = "6001600055";
+        let cold = run_charged(code, Fork::Prague, |_| {}).gas_used;
+        let warm = run_charged(code, Fork::Prague, warm_slot).gas_used;
+
+        assert_eq!(cold - warm, 2100);
+    }
+
+    #[test]
+    fn account_access_charges_cold_accounts() {
+        // BALANCE, EXTCODESIZE, EXTCODEHASH and EXTCODECOPY of 0x1234
+        for code in ["61123431", "6112343b", "6112343f", "6000600060006112343c"] {
+            let cold = run_charged(code, Fork::Prague, |_| {}).gas_used;
+            let warm = run_charged(code, Fork::Prague, warm_account).gas_used;
+            assert_eq!(cold - warm, 2600 - 100, "{}", code);
+        }
+    }
+
+    #[test]
+    fn precompiles_are_always_warm() {
+        // BALANCE of 0x01
+        let cold = run_charged("600131", Fork::Prague, |_| {}).gas_used;
+        let warm = run_charged("600131", Fork::Prague, |access_set| {
+            access_set.access_address(H160::from_low_u64_be(1));
+        });
+
+        assert_eq!(cold, warm.gas_used);
+    }
+
+    fn delegating_state(delegate_code: &str) -> State {
+        let mut state = State::new();
+        let delegate = H160::from_low_u64_be(0x5678);
+        state.add_account(precompile_address(), 0.into(), delegation_designator(delegate));
+        state.add_account(delegate, 0.into(), hex::decode(delegate_code).unwrap());
+        state
+    }
+
+    #[test]
+    fn calls_charge_the_whole_access_cost_of_the_delegate() {
+        // CALL to 0x1234, which delegates to 0x5678
+        let code = "6000600060006000600061123461fffff1";
+        let run_delegating = |mut state: State, warm: fn(&mut AccessSet)| {
+            let res = run_with(code, &mut state, Block::default(), |context| {
+                context.access_set.charge_cold_access = true;
+                warm(&mut context.access_set);
+            });
+            res.gas_used
+        };
+        let warm_both = |access_set: &mut AccessSet| {
+            access_set.access_address(H160::from_low_u64_be(0x1234));
+            access_set.access_address(H160::from_low_u64_be(0x5678));
+        };
+        let undelegated = run_delegating(State::new(), warm_account);
+
+        assert_eq!(run_delegating(delegating_state(""), warm_both) - undelegated, 100);
+        assert_eq!(run_delegating(delegating_state(""), warm_account) - undelegated, 2600);
+        assert_eq!(run_delegating(delegating_state(""), |_| {}) - undelegated, 2600 - 100 + 2600);
+    }
+
+    #[test]
+    fn delegation_is_followed_one_level_only() {
+        // RETURN(MSTORE(0, 1))
+        let mut state = delegating_state("600160005260206000f3");
+        let res = run(&call_0x1234(0), &mut state, Block::default());
+        assert_eq!(res.stack, vec![1.into(), 32.into(), 1.into()]);
+
+        // the delegate delegating in turn runs its designator, which is invalid code
+        let mut state = delegating_state(&hex::encode(delegation_designator(H160::repeat_byte(0x99))));
+        let code = hex::decode("600160005260206000f3").unwrap();
+        state.add_account(H160::repeat_byte(0x99), 0.into(), code);
+        let res = run(&call_0x1234(0), &mut state, Block::default());
+        assert_eq!(res.stack, vec![0.into(), 0.into(), 0.into()]);
+
+        // and before Prague the designator isn't followed at all
+        let block = Block {
+            fork: Fork::Cancun,
+            ..Default::default()
+        };
+        let res = run(&call_0x1234(0), &mut delegating_state("600160005260206000f3"), block);
+        assert_eq!(res.stack, vec![0.into(), 0.into(), 0.into()]);
+    }
+
+    #[test]
+    fn cold_access_is_free_before_berlin_or_when_not_charged() {
+        let cold = run_charged("6001600055", Fork::Istanbul, |_| {}).gas_used;
+        assert_eq!(cold, run_charged("6001600055", Fork::Istanbul, warm_slot).gas_used);
+        let cold = run_charged("6112343b", Fork::Istanbul, |_| {}).gas_used;
+        assert_eq!(cold, run_charged("6112343b", Fork::Istanbul, warm_account).gas_used);
+
+        // nor is anything else
+        let res = run("6001600055", &mut State::new(), Block::default());
+        assert_eq!(res.gas_used, 0);
+    }
+
+    #[test]
+    fn charged_executions_pay_at_least_a_gas_per_opcode() {
+        // PUSH1 1, PUSH1 0, SSTORE and STOP, which is free
+        let res = run_charged("600160005500", Fork::Prague, warm_slot);
+        assert_eq!(res.gas_used, 3);
+
+        // JUMPDEST, JUMP(0) forever
+        let res = run_with("5b600056", &mut State::new(), Block::default(), |context| {
+            context.access_set.charge_cold_access = true;
+            context.gas_limit = 10_000;
+        });
+        assert!(matches!(res.error, Some(EvmError::OutOfGas)));
+        assert_eq!(res.gas_used, 10_000);
+    }
+}
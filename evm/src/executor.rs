@@ -0,0 +1,325 @@
+use std::fmt::Debug;
+
+use primitive_types::H256;
+
+use crate::block::{Block, Withdrawal};
+use crate::database::Database;
+use crate::fork::Fork;
+use crate::precompiles::NativePrecompiles;
+use crate::receipt::{Bloom, Receipt};
+use crate::state::State;
+use crate::system_calls::{apply_system_calls, SystemCallFailure};
+use crate::transaction::{transact, Transaction, TransactionError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockExecutionError {
+    InvalidTransaction {
+        index: usize,
+        error: TransactionError,
+    },
+    // The transaction's gas limit is more than what is left of the block's
+    BlockGasLimitExceeded {
+        index: usize,
+        gas_limit: u64,
+        available: u64,
+    },
+    // The database failed, the block can't be executed at all rather than being invalid
+    Database(String),
+}
+
+impl BlockExecutionError {
+    pub(crate) fn database(err: impl Debug) -> Self {
+        Self::Database(format!("{:?}", err))
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockResult<D = State> {
+    pub receipts: Vec<Receipt>,
+    pub gas_used: u64,
+    pub logs_bloom: Bloom,
+    // Transactions left out by build_block, always empty for execute_block
+    pub skipped: Vec<BlockExecutionError>,
+    // System calls whose contract failed, which doesn't make the block invalid
+    pub system_call_failures: Vec<SystemCallFailure>,
+    pub state_root: H256,
+    pub state: D,
+}
+
+// Executes a block's system calls, its transactions in order and then its withdrawals. Fails if any of the
+// transactions can't be included, as a valid block never contains such a transaction
+pub fn execute_block<D: Database>(
+    block: &Block,
+    transactions: &[Transaction],
+    withdrawals: &[Withdrawal],
+    state: D,
+    native_precompiles: &NativePrecompiles,
+) -> Result<BlockResult<D>, BlockExecutionError> {
+    apply_block(block, transactions, withdrawals, state, native_precompiles, false)
+}
+
+// As execute_block, but transactions which can't be included are skipped rather than failing the block,
+// as when building a block from a pool of pending transactions. Only the database failing is an error
+pub fn build_block<D: Database>(
+    block: &Block,
+    transactions: &[Transaction],
+    withdrawals: &[Withdrawal],
+    state: D,
+    native_precompiles: &NativePrecompiles,
+) -> Result<BlockResult<D>, BlockExecutionError> {
+    apply_block(block, transactions, withdrawals, state, native_precompiles, true)
+}
+
+fn apply_block<D: Database>(
+    block: &Block,
+    transactions: &[Transaction],
+    withdrawals: &[Withdrawal],
+    mut state: D,
+    native_precompiles: &NativePrecompiles,
+    skip_invalid: bool,
+) -> Result<BlockResult<D>, BlockExecutionError> {
+    let system_call_failures = apply_system_calls(block, &mut state)?;
+
+    let block_gas_limit = block.gas_limit();
+
+    let mut receipts: Vec<Receipt> = Vec::new();
+    let mut skipped = Vec::new();
+    let mut gas_used = 0;
+    let mut logs_bloom = Bloom::default();
+
+    for (index, tx) in transactions.iter().enumerate() {
+        let available = block_gas_limit - gas_used;
+        let result = if tx.gas_limit > available {
+            Err(BlockExecutionError::BlockGasLimitExceeded {
+                index,
+                gas_limit: tx.gas_limit,
+                available,
+            })
+        } else {
+            // an invalid transaction leaves the state as it was
+            transact(tx, block, &mut state, native_precompiles).map_err(|error| match error {
+                TransactionError::Database(err) => BlockExecutionError::Database(err),
+                error => BlockExecutionError::InvalidTransaction { index, error },
+            })
+        };
+
+        match result {
+            Ok(mut receipt) => {
+                gas_used += receipt.gas_used;
+                receipt.cumulative_gas_used = gas_used;
+                logs_bloom.accrue_bloom(&receipt.logs_bloom);
+                receipts.push(receipt);
+            }
+            Err(BlockExecutionError::Database(err)) => return Err(BlockExecutionError::Database(err)),
+            Err(err) if skip_invalid => skipped.push(err),
+            Err(err) => return Err(err),
+        }
+    }
+
+    apply_withdrawals(block, withdrawals, &mut state)?;
+
+    Ok(BlockResult {
+        receipts,
+        gas_used,
+        logs_bloom,
+        skipped,
+        system_call_failures,
+        state_root: state.state_root().map_err(BlockExecutionError::database)?,
+        state,
+    })
+}
+
+// EIP-4895: withdrawals only exist from Shanghai, and can't fail. Zero amounts are skipped rather than
+// touching the account, as an empty account would be removed again anyway
+fn apply_withdrawals<D: Database>(
+    block: &Block,
+    withdrawals: &[Withdrawal],
+    state: &mut D,
+) -> Result<(), BlockExecutionError> {
+    if !block.fork.is_enabled(Fork::Shanghai) {
+        return Ok(());
+    }
+
+    for withdrawal in withdrawals {
+        if withdrawal.amount > 0 {
+            state
+                .increment_balance(withdrawal.address, withdrawal.amount_wei())
+                .map_err(BlockExecutionError::database)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precompiles::NativeOutput;
+    use crate::transaction::{TxFees, TxKind, TxType};
+    use primitive_types::{H160, U256};
+
+    // Without native precompiles, which most tests don't need
+    fn execute_block(
+        block: &Block,
+        transactions: &[Transaction],
+        withdrawals: &[Withdrawal],
+        state: State,
+    ) -> Result<BlockResult, BlockExecutionError> {
+        super::execute_block(block, transactions, withdrawals, state, &NativePrecompiles::new())
+    }
+
+    fn build_block(
+        block: &Block,
+        transactions: &[Transaction],
+        withdrawals: &[Withdrawal],
+        state: State,
+    ) -> Result<BlockResult, BlockExecutionError> {
+        super::build_block(block, transactions, withdrawals, state, &NativePrecompiles::new())
+    }
+
+    fn sender() -> H160 {
+        H160::repeat_byte(0x11)
+    }
+
+    fn recipient() -> H160 {
+        H160::repeat_byte(0x22)
+    }
+
+    fn block(fork: Fork) -> Block {
+        Block {
+            coinbase: H160::repeat_byte(0x33),
+            gaslimit: 100_000.into(),
+            fork,
+            ..Default::default()
+        }
+    }
+
+    fn transfer(nonce: u64) -> Transaction {
+        Transaction {
+            tx_type: TxType::Legacy,
+            from: sender(),
+            to: TxKind::Call(recipient()),
+            value: 100.into(),
+            data: Vec::new(),
+            gas_limit: 21000,
+            fees: TxFees::Legacy {
+                gas_price: 10.into(),
+            },
+            nonce,
+            access_list: Vec::new(),
+            max_fee_per_blob_gas: U256::zero(),
+            blob_versioned_hashes: Vec::new(),
+            authorization_list: Vec::new(),
+        }
+    }
+
+    fn state() -> State {
+        let mut state = State::new();
+        state.add_account(sender(), 1_000_000.into(), Vec::new());
+        state
+    }
+
+    fn withdrawal(amount: u64) -> Withdrawal {
+        Withdrawal {
+            index: 0,
+            validator_index: 0,
+            address: H160::repeat_byte(0x44),
+            amount,
+        }
+    }
+
+    #[test]
+    fn receipts_accumulate_the_gas_used() {
+        let transactions = [transfer(0), transfer(1)];
+
+        let res = execute_block(&block(Fork::Prague), &transactions, &[], state()).unwrap();
+
+        let cumulative: Vec<u64> = res
+            .receipts
+            .iter()
+            .map(|receipt| receipt.cumulative_gas_used)
+            .collect();
+        assert_eq!(cumulative, vec![21000, 42000]);
+        assert_eq!(res.gas_used, 42000);
+        assert!(res.skipped.is_empty());
+        assert_eq!(res.state.balance(recipient()).unwrap(), 200.into());
+        assert_eq!(res.state.nonce(sender()).unwrap(), 2);
+        assert_eq!(res.state_root, res.state.state_root().unwrap());
+    }
+
+    #[test]
+    fn invalid_transaction_fails_execution_but_is_skipped_when_building() {
+        // The second transaction reuses the first one's nonce
+        let transactions = [transfer(0), transfer(0), transfer(1)];
+
+        let err = execute_block(&block(Fork::Prague), &transactions, &[], state()).err().unwrap();
+        assert_eq!(
+            err,
+            BlockExecutionError::InvalidTransaction {
+                index: 1,
+                error: TransactionError::NonceMismatch {
+                    expected: 1,
+                    actual: 0,
+                },
+            }
+        );
+
+        let res = build_block(&block(Fork::Prague), &transactions, &[], state()).unwrap();
+        assert_eq!(res.receipts.len(), 2);
+        assert_eq!(res.skipped, vec![err]);
+        assert_eq!(res.gas_used, 42000);
+        assert_eq!(res.state.nonce(sender()).unwrap(), 2);
+    }
+
+    #[test]
+    fn transactions_over_the_block_gas_limit_are_rejected() {
+        let transactions = [transfer(0), transfer(1), transfer(2), transfer(3), transfer(4)];
+
+        let err = execute_block(&block(Fork::Prague), &transactions, &[], state()).err().unwrap();
+
+        assert_eq!(
+            err,
+            BlockExecutionError::BlockGasLimitExceeded {
+                index: 4,
+                gas_limit: 21000,
+                available: 100_000 - 4 * 21000,
+            }
+        );
+    }
+
+    #[test]
+    fn withdrawals_credit_gwei_and_skip_zero_amounts() {
+        let res = execute_block(&block(Fork::Shanghai), &[], &[withdrawal(3)], State::new()).unwrap();
+        assert_eq!(res.state.balance(withdrawal(3).address).unwrap(), U256::from(3_000_000_000u64));
+
+        let res = execute_block(&block(Fork::Shanghai), &[], &[withdrawal(0)], State::new()).unwrap();
+        assert!(res.state.get_account(withdrawal(0).address).is_none());
+    }
+
+    #[test]
+    fn withdrawals_are_ignored_before_shanghai() {
+        let res = execute_block(&block(Fork::London), &[], &[withdrawal(3)], State::new()).unwrap();
+
+        assert!(res.state.get_account(withdrawal(3).address).is_none());
+    }
+
+    #[test]
+    fn native_precompiles_are_available_to_the_transactions() {
+        let mut state = state();
+        // CALL 0x44, then INVALID unless the call succeeded
+        let code = hex::decode("6000600060006000600060445af1601257fe5b00").unwrap();
+        state.add_account(recipient(), 0.into(), code);
+        let mut tx = transfer(0);
+        tx.gas_limit = 50_000;
+        let mut native_precompiles = NativePrecompiles::new();
+        native_precompiles.register(H160::from_low_u64_be(0x44), |_: &[u8], _| NativeOutput {
+            output: Vec::new(),
+            gas_used: 0,
+            success: false,
+        });
+
+        let block = block(Fork::Prague);
+        let res = super::execute_block(&block, &[tx], &[], state, &native_precompiles).unwrap();
+
+        assert!(!res.receipts[0].success);
+    }
+}
@@ -0,0 +1,102 @@
+use primitive_types::U256;
+
+// EIP-1559
+pub const ELASTICITY_MULTIPLIER: u64 = 2;
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+
+// The price actually paid per unit of gas: the base fee plus as much of the priority fee as the cap allows
+pub fn effective_gas_price(
+    max_fee_per_gas: U256,
+    max_priority_fee_per_gas: U256,
+    basefee: U256,
+) -> U256 {
+    max_fee_per_gas.min(basefee.saturating_add(max_priority_fee_per_gas))
+}
+
+// How the fee for gas_used is split, the burnt part is removed from circulation and the tip goes to the coinbase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSplit {
+    pub burnt: U256,
+    pub tip: U256,
+}
+
+// Before London there is no base fee, so basefee should be zero and the whole fee is tip. A valid
+// transaction never pays less than the base fee, if one does what it paid is burnt and there is no tip
+pub fn split_fee(gas_used: u64, effective_gas_price: U256, basefee: U256) -> FeeSplit {
+    let gas_used = U256::from(gas_used);
+    FeeSplit {
+        burnt: gas_used.saturating_mul(basefee.min(effective_gas_price)),
+        tip: gas_used.saturating_mul(effective_gas_price.saturating_sub(basefee)),
+    }
+}
+
+// The base fee moves by at most 1/8 per block, towards keeping blocks at the gas target
+pub fn next_base_fee(parent_basefee: U256, parent_gas_used: u64, parent_gas_target: u64) -> U256 {
+    if parent_gas_target == 0 || parent_gas_used == parent_gas_target {
+        return parent_basefee;
+    }
+
+    let target = U256::from(parent_gas_target);
+    let denominator = U256::from(BASE_FEE_MAX_CHANGE_DENOMINATOR);
+
+    if parent_gas_used > parent_gas_target {
+        let delta = U256::from(parent_gas_used - parent_gas_target);
+        let increase = (parent_basefee * delta / target / denominator).max(U256::one());
+        parent_basefee + increase
+    } else {
+        let delta = U256::from(parent_gas_target - parent_gas_used);
+        let decrease = parent_basefee * delta / target / denominator;
+        parent_basefee - decrease
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INITIAL_BASE_FEE: u64 = 1_000_000_000;
+
+    #[test]
+    fn base_fee_is_unchanged_at_the_target() {
+        let basefee = next_base_fee(INITIAL_BASE_FEE.into(), 10_000_000, 10_000_000);
+
+        assert_eq!(basefee, INITIAL_BASE_FEE.into());
+    }
+
+    #[test]
+    fn base_fee_increases_above_the_target() {
+        assert_eq!(next_base_fee(INITIAL_BASE_FEE.into(), 11_000_000, 10_000_000), 1_012_500_000.into());
+        // a full block raises it by the most, an eighth
+        assert_eq!(next_base_fee(INITIAL_BASE_FEE.into(), 20_000_000, 10_000_000), 1_125_000_000.into());
+    }
+
+    #[test]
+    fn base_fee_decreases_below_the_target() {
+        assert_eq!(next_base_fee(INITIAL_BASE_FEE.into(), 9_000_000, 10_000_000), 987_500_000.into());
+        // an empty block lowers it by the most, an eighth
+        assert_eq!(next_base_fee(INITIAL_BASE_FEE.into(), 0, 10_000_000), 875_000_000.into());
+    }
+
+    #[test]
+    fn base_fee_increases_by_at_least_one() {
+        assert_eq!(next_base_fee(7.into(), 10_000_001, 10_000_000), 8.into());
+        // while a decrease can round down to nothing
+        assert_eq!(next_base_fee(7.into(), 9_999_999, 10_000_000), 7.into());
+    }
+
+    #[test]
+    fn splits_the_fee_between_burning_and_the_tip() {
+        let split = split_fee(21000, 12.into(), 10.into());
+
+        assert_eq!(split.burnt, (21000 * 10).into());
+        assert_eq!(split.tip, (21000 * 2).into());
+    }
+
+    #[test]
+    fn price_below_the_base_fee_burns_what_was_paid() {
+        let split = split_fee(21000, 8.into(), 10.into());
+
+        assert_eq!(split.burnt, (21000 * 8).into());
+        assert_eq!(split.tip, U256::zero());
+    }
+}
@@ -0,0 +1,21 @@
+use serde::Deserialize;
+
+// Hard forks in activation order, only those which change the behaviour of this implementation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum Fork {
+    Byzantium,
+    Istanbul,
+    Berlin,
+    London,
+    Paris,
+    Shanghai,
+    Cancun,
+    #[default]
+    Prague,
+}
+
+impl Fork {
+    pub fn is_enabled(&self, fork: Fork) -> bool {
+        *self >= fork
+    }
+}
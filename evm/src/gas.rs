@@ -0,0 +1,46 @@
+use crate::machine::EvmError;
+
+// Every opcode other than STOP, RETURN and REVERT costs at least this much. Opcodes aren't metered
+// yet, so each is charged this instead, which bounds execution by the gas limit without ever
+// charging more than the full cost would
+pub const MIN_OPCODE_COST: u64 = 1;
+
+// Only costs that are explicitly modelled are recorded here, on top of MIN_OPCODE_COST per opcode.
+// The gas used is therefore a lower bound of what the specification charges, not the same figure
+pub struct Gas {
+    limit: u64,
+    used: u64,
+}
+
+impl Gas {
+    pub fn new(limit: u64) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.limit - self.used
+    }
+
+    pub fn record_cost(&mut self, cost: u64) -> Result<(), EvmError> {
+        match self.used.checked_add(cost) {
+            Some(used) if used <= self.limit => {
+                self.used = used;
+                Ok(())
+            }
+            _ => Err(EvmError::OutOfGas),
+        }
+    }
+}
+
+// EIP-150: a sub context can be given at most all but one 64th of the remaining gas
+pub fn all_but_one_64th(gas: u64) -> u64 {
+    gas - gas / 64
+}
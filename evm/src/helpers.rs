@@ -1,5 +1,8 @@
+use crate::consts::{CODE_DEPOSIT_COST, MAX_CODE_SIZE};
+use crate::database::Database;
+use crate::fork::Fork;
 use crate::machine::{ControlFlow, EvmError, ExitReason, ExitSuccess};
-use primitive_types::{H160, H256, U256};
+use primitive_types::{H160, H256, U256, U512};
 use sha3::{Digest, Keccak256};
 use std::ops::{Add, Div, Sub};
 
@@ -7,6 +10,7 @@ use std::ops::{Add, Div, Sub};
 // TODO: extract traits into own file
 pub trait Convert {
     fn to_h160(&self) -> H160;
+    fn to_h256(&self) -> H256;
     fn to_u256(&self) -> U256;
 }
 
@@ -19,7 +23,12 @@ impl Convert for U256 {
     fn to_h160(&self) -> H160 {
         let mut bytes: [u8; 32] = [0; 32];
         self.to_big_endian(&mut bytes);
-        H160::from_slice(&remove_padding(&bytes))
+        H160::from_slice(&bytes[12..])
+    }
+    fn to_h256(&self) -> H256 {
+        let mut bytes: [u8; 32] = [0; 32];
+        self.to_big_endian(&mut bytes);
+        H256::from(bytes)
     }
     fn to_u256(&self) -> U256 {
         *self
@@ -29,7 +38,7 @@ impl Convert for U256 {
 impl ToBytes for U256 {
     fn to_vec_u8_without_padding(&self) -> Vec<u8> {
         let mut return_val_bytes: [u8; 32] = [0; 32];
-        U256::to_big_endian(&self, &mut return_val_bytes);
+        U256::to_big_endian(self, &mut return_val_bytes);
         let return_val_without_padding: Vec<u8> = remove_padding(&return_val_bytes);
         return_val_without_padding
     }
@@ -39,6 +48,21 @@ impl Convert for H160 {
     fn to_h160(&self) -> H160 {
         *self
     }
+    fn to_h256(&self) -> H256 {
+        self.to_u256().to_h256()
+    }
+    fn to_u256(&self) -> U256 {
+        U256::from_big_endian(self.as_bytes())
+    }
+}
+
+impl Convert for H256 {
+    fn to_h160(&self) -> H160 {
+        self.to_u256().to_h160()
+    }
+    fn to_h256(&self) -> H256 {
+        *self
+    }
     fn to_u256(&self) -> U256 {
         U256::from_big_endian(self.as_bytes())
     }
@@ -46,22 +70,26 @@ impl Convert for H160 {
 
 impl Convert for String {
     fn to_h160(&self) -> H160 {
-        let hex_decoded = hex_decode_with_prefix(&self);
+        let hex_decoded = hex_decode_with_prefix(self);
         let res = add_padding(&hex_decoded, 20);
         H160::from_slice(&res)
     }
 
+    fn to_h256(&self) -> H256 {
+        self.to_u256().to_h256()
+    }
+
     fn to_u256(&self) -> U256 {
-        let hex_decoded = hex_decode_with_prefix(&self);
+        let hex_decoded = hex_decode_with_prefix(self);
         U256::from_big_endian(&hex_decoded)
     }
 }
 
-pub fn hex_decode_with_prefix(data: &String) -> Vec<u8> {
+pub fn hex_decode_with_prefix(data: &str) -> Vec<u8> {
     let slice = if data.contains('x') {
         &data[2..]
     } else {
-        &data[..]
+        data
     };
 
     let mut res = String::new();
@@ -73,14 +101,46 @@ pub fn hex_decode_with_prefix(data: &String) -> Vec<u8> {
 }
 
 pub fn remove_padding(list: &[u8]) -> Vec<u8> {
-    list.to_vec().into_iter().skip_while(|x| *x == 0).collect()
+    list.iter().skip_while(|x| **x == 0).copied().collect()
 }
 
 pub fn create_address(caller: H160, nonce: U256) -> H160 {
     let mut stream = rlp::RlpStream::new_list(2);
     stream.append(&caller);
     stream.append(&nonce);
-    H256::from_slice(Keccak256::digest(&stream.out()).as_slice()).into()
+    H256::from_slice(Keccak256::digest(stream.out()).as_slice()).into()
+}
+
+pub fn create2_address(caller: H160, salt: U256, initialisation_code: &[u8]) -> H160 {
+    let mut hasher = Keccak256::new();
+    hasher.update([0xff]);
+    hasher.update(caller.as_bytes());
+    hasher.update(salt.to_h256().as_bytes());
+    hasher.update(Keccak256::digest(initialisation_code));
+    H256::from_slice(hasher.finalize().as_slice()).into()
+}
+
+// Returns the code deposit cost if the code returned by a contract's initialisation code can be deployed
+pub fn check_deployed_code(code: &[u8], gas_remaining: u64, fork: Fork) -> Result<u64, EvmError> {
+    if code.len() > MAX_CODE_SIZE {
+        return Err(EvmError::CodeSizeExceeded);
+    }
+    // EIP-3541
+    if fork.is_enabled(Fork::London) && code.first() == Some(&0xef) {
+        return Err(EvmError::InvalidCodePrefix);
+    }
+
+    let deposit_cost = CODE_DEPOSIT_COST * code.len() as u64;
+    if deposit_cost > gas_remaining {
+        return Err(EvmError::OutOfGas);
+    }
+
+    Ok(deposit_cost)
+}
+
+// EIP-684: an account with code or a nonce can't be created again
+pub fn has_contract<D: Database>(state: &D, address: H160) -> Result<bool, D::Error> {
+    Ok(state.nonce(address)? != 0 || !state.code(address)?.is_empty())
 }
 
 pub fn exit_error(err: EvmError) -> ControlFlow {
@@ -96,19 +156,16 @@ pub fn add_padding(arr: &[u8], size: usize) -> Vec<u8> {
     }
     let mut res = vec![0; size];
     let start_index = size - arr.len();
-    for (arr_index, res_index) in (start_index..res.len()).into_iter().enumerate() {
-        res[res_index] = arr[arr_index];
-    }
+    res[start_index..].copy_from_slice(arr);
     res
 }
 
 pub fn arr_slice_extend(arr: &[u8], offset: usize, size: usize) -> U256 {
     let mut res = vec![0; size];
-    for i in 0..size {
+    for (i, byte) in res.iter_mut().enumerate() {
         let code_index = i + offset;
         if code_index < arr.len() {
-            let data = arr[code_index];
-            res[i] = data;
+            *byte = arr[code_index];
         }
     }
     U256::from_big_endian(&res)
@@ -132,6 +189,29 @@ pub fn is_negative(x: U256) -> bool {
     x.bit(255)
 }
 
+// Approximates factor * e ** (numerator / denominator) using a Taylor expansion, as in the EIP-4844 reference.
+// The loop stops once a term rounds down to zero, or once the result no longer fits a U256, which bounds it to
+// a few hundred iterations whatever the inputs
+pub fn fake_exponential(factor: u64, numerator: u64, denominator: u64) -> U256 {
+    let numerator = U512::from(numerator);
+    let denominator = U512::from(denominator);
+    let limit = U512::from(U256::MAX) * denominator;
+
+    let mut i = 1u64;
+    let mut output = U512::zero();
+    let mut numerator_accum = U512::from(factor) * denominator;
+    while !numerator_accum.is_zero() {
+        output += numerator_accum;
+        if output > limit {
+            return U256::MAX;
+        }
+        numerator_accum = numerator_accum * numerator / (denominator * U512::from(i));
+        i += 1;
+    }
+
+    (output / denominator).try_into().unwrap()
+}
+
 pub fn ceil_divide<T: Int>(a: T, b: T) -> T {
     (a + b - T::one()) / b
 }
@@ -169,3 +249,36 @@ impl Int for u32 {
         1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_exponential_matches_the_reference_vectors() {
+        let vectors = [
+            (1, 2, 1, 6u64),
+            (1, 4, 2, 6),
+            (1, 3, 1, 16),
+            (1, 6, 2, 18),
+            (1, 4, 1, 49),
+            (1, 8, 2, 50),
+            (10, 8, 2, 542),
+            (11, 8, 2, 596),
+            (1, 5, 1, 136),
+            (1, 5, 2, 11),
+            (2, 5, 2, 23),
+            (1, 50000000, 2225652, 5709098764),
+            (1, 380928, 3338477, 1),
+        ];
+        for (factor, numerator, denominator, expected) in vectors {
+            assert_eq!(fake_exponential(factor, numerator, denominator), expected.into());
+        }
+    }
+
+    #[test]
+    fn fake_exponential_saturates_on_huge_exponents() {
+        assert_eq!(fake_exponential(1, u64::MAX, 3338477), U256::MAX);
+        assert_eq!(fake_exponential(u64::MAX, u64::MAX, 1), U256::MAX);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::authorization::Authorization;
+use crate::consts::{INITCODE_WORD_COST, PER_EMPTY_ACCOUNT_COST, WORD_BYTES};
+use crate::fork::Fork;
+use crate::helpers::ceil_divide;
+use crate::transaction::AccessListItem;
+
+const TX_BASE_COST: u64 = 21000;
+const TX_CREATE_COST: u64 = 32000;
+const TX_DATA_ZERO_COST: u64 = 4;
+const TX_DATA_NON_ZERO_COST_FRONTIER: u64 = 68;
+// EIP-2028
+const TX_DATA_NON_ZERO_COST: u64 = 16;
+// EIP-2930
+const ACCESS_LIST_ADDRESS_COST: u64 = 2400;
+const ACCESS_LIST_STORAGE_KEY_COST: u64 = 1900;
+// EIP-7623
+const TOTAL_COST_FLOOR_PER_TOKEN: u64 = 10;
+const NON_ZERO_BYTE_TOKENS: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntrinsicGas {
+    // Charged before execution starts
+    pub initial: u64,
+    // EIP-7623: the least a transaction can be charged in total, zero before Prague
+    pub floor: u64,
+}
+
+impl IntrinsicGas {
+    // A transaction with a lower gas limit is invalid
+    pub fn minimum_gas_limit(&self) -> u64 {
+        self.initial.max(self.floor)
+    }
+}
+
+pub fn intrinsic_gas(
+    data: &[u8],
+    is_create: bool,
+    access_list: &[AccessListItem],
+    authorization_list: &[Authorization],
+    fork: Fork,
+) -> IntrinsicGas {
+    let zero_bytes = data.iter().filter(|&&byte| byte == 0).count() as u64;
+    let non_zero_bytes = data.len() as u64 - zero_bytes;
+
+    let non_zero_cost = if fork.is_enabled(Fork::Istanbul) {
+        TX_DATA_NON_ZERO_COST
+    } else {
+        TX_DATA_NON_ZERO_COST_FRONTIER
+    };
+    let mut initial = TX_BASE_COST + zero_bytes * TX_DATA_ZERO_COST + non_zero_bytes * non_zero_cost;
+
+    if is_create {
+        initial += TX_CREATE_COST;
+        // EIP-3860
+        if fork.is_enabled(Fork::Shanghai) {
+            initial += INITCODE_WORD_COST * ceil_divide(data.len(), WORD_BYTES) as u64;
+        }
+    }
+
+    if fork.is_enabled(Fork::Berlin) {
+        for item in access_list {
+            initial += ACCESS_LIST_ADDRESS_COST
+                + ACCESS_LIST_STORAGE_KEY_COST * item.storage_keys.len() as u64;
+        }
+    }
+
+    // EIP-7702: each authorization is paid for as if it created an account, which is partly refunded if not
+    if fork.is_enabled(Fork::Prague) {
+        initial += PER_EMPTY_ACCOUNT_COST * authorization_list.len() as u64;
+    }
+
+    let floor = if fork.is_enabled(Fork::Prague) {
+        let tokens = zero_bytes + non_zero_bytes * NON_ZERO_BYTE_TOKENS;
+        TX_BASE_COST + TOTAL_COST_FLOOR_PER_TOKEN * tokens
+    } else {
+        0
+    };
+
+    IntrinsicGas { initial, floor }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use primitive_types::{H160, H256, U256};
+
+    fn call_gas(data: &[u8], fork: Fork) -> IntrinsicGas {
+        intrinsic_gas(data, false, &[], &[], fork)
+    }
+
+    #[test]
+    fn charges_zero_and_non_zero_calldata_bytes() {
+        let data = [0, 0, 1, 2, 0];
+
+        assert_eq!(call_gas(&data, Fork::Istanbul).initial, 21000 + 3 * 4 + 2 * 16);
+        assert_eq!(call_gas(&data, Fork::Byzantium).initial, 21000 + 3 * 4 + 2 * 68);
+    }
+
+    #[test]
+    fn charges_creation_and_initcode_words() {
+        let data = [0xff; 33];
+        let create = |fork| intrinsic_gas(&data, true, &[], &[], fork).initial;
+
+        assert_eq!(create(Fork::Shanghai), 21000 + 33 * 16 + 32000 + 2 * 2);
+        assert_eq!(create(Fork::London), 21000 + 33 * 16 + 32000);
+    }
+
+    #[test]
+    fn charges_access_list_entries() {
+        let access_list = [
+            AccessListItem {
+                address: H160::repeat_byte(1),
+                storage_keys: vec![H256::zero(), H256::repeat_byte(1)],
+            },
+            AccessListItem {
+                address: H160::repeat_byte(2),
+                storage_keys: vec![H256::zero()],
+            },
+        ];
+        let gas = |fork| intrinsic_gas(&[], false, &access_list, &[], fork).initial;
+
+        assert_eq!(gas(Fork::Berlin), 21000 + 2 * 2400 + 3 * 1900);
+        assert_eq!(gas(Fork::Istanbul), 21000);
+    }
+
+    #[test]
+    fn charges_authorizations_from_prague() {
+        let authorization_list = [Authorization {
+            chain_id: U256::zero(),
+            address: H160::repeat_byte(1),
+            nonce: 0,
+            y_parity: 0,
+            r: U256::one(),
+            s: U256::one(),
+        }];
+        let gas = |fork| intrinsic_gas(&[], false, &[], &authorization_list, fork).initial;
+
+        assert_eq!(gas(Fork::Prague), 21000 + 25000);
+        assert_eq!(gas(Fork::Cancun), 21000);
+    }
+
+    #[test]
+    fn calldata_floor_applies_from_prague() {
+        let data = [0xff; 100];
+
+        let prague = call_gas(&data, Fork::Prague);
+        assert_eq!(prague.initial, 21000 + 100 * 16);
+        assert_eq!(prague.floor, 21000 + 10 * 100 * 4);
+        assert_eq!(prague.minimum_gas_limit(), prague.floor);
+
+        let cancun = call_gas(&data, Fork::Cancun);
+        assert_eq!(cancun.floor, 0);
+        assert_eq!(cancun.minimum_gas_limit(), cancun.initial);
+    }
+
+    #[test]
+    fn zero_bytes_count_as_a_single_floor_token() {
+        assert_eq!(call_gas(&[0; 10], Fork::Prague).floor, 21000 + 10 * 10);
+    }
+}
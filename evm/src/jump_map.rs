@@ -12,18 +12,11 @@ impl JumpMap {
     fn generate_map(code: &[u8]) -> Vec<bool> {
         let mut map: Vec<bool> = vec![];
         let mut steps_to_block = 0;
-        for i in 0..code.len() {
-            if steps_to_block > 0 {
-                steps_to_block -= 1;
-            }
-
-            let opcode = code[i];
+        for &opcode in code {
+            steps_to_block = usize::saturating_sub(steps_to_block, 1);
 
-            match opcode {
-                Opcode::PUSH1..=Opcode::PUSH32 => {
-                    steps_to_block = usize::from(opcode) - usize::from(Opcode::PUSH1) + 2;
-                }
-                _ => (),
+            if let Opcode::PUSH1..=Opcode::PUSH32 = opcode {
+                steps_to_block = usize::from(opcode) - usize::from(Opcode::PUSH1) + 2;
             }
 
             if steps_to_block == 0 && opcode == Opcode::JUMPDEST {
@@ -1,33 +1,41 @@
+pub mod access_list;
+pub mod authorization;
 pub mod block;
 mod consts;
 pub mod context;
+pub mod database;
 mod eval;
+pub mod executor;
+pub mod fees;
+pub mod fork;
+mod gas;
 pub mod helpers;
+pub mod intrinsic_gas;
 mod jump_map;
 mod machine;
 mod memory;
 mod opcode;
+pub mod precompiles;
+pub mod receipt;
 mod stack;
 pub mod state;
+pub mod system_calls;
+pub mod transaction;
+pub mod trie;
+
+pub use crate::machine::{EvmError, EvmResult, Log};
 
-use std::collections::HashMap;
-use primitive_types::U256;
 use crate::block::Block;
 use crate::context::Context;
-use crate::machine::EvmResult;
+use crate::database::Database;
 use crate::machine::Machine;
 
 // TODO: refactor to create Runtime struct with Machine within it
-pub fn evm(
+pub fn evm<D: Database>(
     code: impl AsRef<[u8]>,
-    context: Context,
+    context: Context<D>,
     block: Block,
-    storage: Option<&mut HashMap<U256, U256>>,
 ) -> EvmResult {
-    let mut new_storage = HashMap::new();
-    let mut machine: Machine = match storage {
-        Some(store) => Machine::new(code.as_ref(), context, block, store),
-        None => Machine::new(code.as_ref(), context, block, &mut new_storage)
-    };
-    return machine.execute();
+    let mut machine = Machine::new(code.as_ref(), context, block);
+    machine.execute()
 }
@@ -1,12 +1,17 @@
-use std::collections::HashMap;
-
+use crate::access_list::AccessSet;
 use crate::{block::Block, context::Context};
+use crate::database::Database;
 use crate::eval::eval;
+use crate::gas::{Gas, MIN_OPCODE_COST};
+use crate::helpers::Convert;
 use crate::jump_map::JumpMap;
 use crate::memory::Memory;
+use crate::opcode::Opcode;
 use crate::stack::Stack;
 use crate::state::State;
-use primitive_types::{U256, H160};
+use primitive_types::{U256, H160, H256};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 pub enum ControlFlow {
     Continue(usize),
@@ -21,16 +26,26 @@ pub enum ExitReason {
 
 pub enum ExitSuccess {
     Stop,
-    Return(U256),
+    Return(Vec<u8>),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub enum EvmError {
     StackUnderflow,
     InvalidInstruction,
     InvalidJump,
-    Revert(U256),
+    Revert(Vec<u8>),
     OpcodeNotStatic(u8),
+    OutOfGas,
+    // The database failed, which fails every frame up to the transaction
+    Database(String),
+    InitcodeSizeExceeded,
+    // EIP-684
+    CreateCollision,
+    CodeSizeExceeded,
+    InvalidCodePrefix,
+    ReturnDataOutOfBounds,
+    PrecompileFailure,
 }
 
 enum EvmStatus {
@@ -38,72 +53,108 @@ enum EvmStatus {
     Exited(ExitReason),
 }
 
+// The changes to the state are made in the database the call was given
 pub struct EvmResult {
     pub stack: Vec<U256>,
     pub success: bool,
     pub error: Option<EvmError>,
     pub logs: Vec<Log>,
-    pub state: State,
-    pub return_val: Option<U256>,
+    pub access_set: AccessSet,
+    pub return_val: Option<Vec<u8>>,
+    pub gas_used: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "HexLog", try_from = "HexLog")]
 pub struct Log {
-    pub address: String,
-    pub data: String,
-    pub topics: Vec<String>,
+    pub address: H160,
+    pub topics: Vec<H256>,
+    pub data: Vec<u8>,
 }
 
 impl Log {
     pub fn new(address: H160, data: &[u8]) -> Self {
         Self {
-            address: address.to_string(),
-            data: hex::encode(data),
+            address,
             topics: Vec::new(),
+            data: data.to_vec(),
         }
     }
 
     pub fn add_topic(&mut self, topic: U256) {
-        let mut bytes: [u8; 32] = [0; 32];
-        topic.to_big_endian(&mut bytes);
+        self.topics.push(topic.to_h256());
+    }
+}
 
-        let topic_string = hex::encode(bytes);
+// Logs are serialized with 0x prefixed hex strings, as in JSON-RPC
+#[derive(Serialize, Deserialize)]
+struct HexLog {
+    address: String,
+    topics: Vec<String>,
+    data: String,
+}
 
-        // TODO: check if this can be done using H160 or similar, maybe H256
-        let mut prepended_topic: String = "0x".to_owned();
-        prepended_topic.push_str(&topic_string);
+impl From<Log> for HexLog {
+    fn from(log: Log) -> Self {
+        Self {
+            address: format!("{:?}", log.address),
+            topics: log.topics.iter().map(|topic| format!("{:?}", topic)).collect(),
+            data: format!("0x{}", hex::encode(log.data)),
+        }
+    }
+}
 
-        self.topics.push(prepended_topic);
+impl TryFrom<HexLog> for Log {
+    type Error = String;
+
+    fn try_from(log: HexLog) -> Result<Self, Self::Error> {
+        let address = H160::from_str(&log.address).map_err(|err| err.to_string())?;
+        let topics = log
+            .topics
+            .iter()
+            .map(|topic| H256::from_str(topic).map_err(|err| err.to_string()))
+            .collect::<Result<_, _>>()?;
+        let data = hex::decode(log.data.strip_prefix("0x").unwrap_or(&log.data))
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            address,
+            topics,
+            data,
+        })
     }
 }
 
-pub struct Machine<'a> {
+pub struct Machine<'a, D = State> {
     pub stack: Stack,
     pub memory: Memory,
-    pub storage: &'a mut HashMap<U256, U256>,
     pub return_data_buffer: Vec<u8>,
-    pub context: Context<'a>,
-    pub block: Block<'a>,
+    pub context: Context<'a, D>,
+    pub block: Block,
     pub jump_map: JumpMap,
     pub code: &'a [u8],
     pub logs: Vec<Log>,
+    pub gas: Gas,
     pub pc: usize,
 }
 
-impl<'a> Machine<'a> {
+impl<'a, D: Database> Machine<'a, D> {
     pub fn new(
         code: &'a [u8],
-        context: Context<'a>,
-        block: Block<'a>,
-        storage: &'a mut HashMap<U256, U256>,
+        mut context: Context<'a, D>,
+        block: Block,
     ) -> Self {
+        // the executing account and its caller have necessarily been accessed already
+        context.access_set.access_address(context.address);
+        context.access_set.access_address(context.caller);
+
         Self {
             stack: Stack::new(),
             memory: Memory::new(),
             jump_map: JumpMap::new(code),
             return_data_buffer: Vec::new(),
             logs: Vec::new(),
-            storage,
+            gas: Gas::new(context.gas_limit),
             context,
             block,
             code,
@@ -120,6 +171,14 @@ impl<'a> Machine<'a> {
     }
 
     fn step(&mut self) -> EvmStatus {
+        // only executions which are charged for accesses, as transactions are, are metered
+        let metered = self.context.access_set.charge_cold_access;
+        if metered && !matches!(self.opcode(), Opcode::STOP | Opcode::RETURN | Opcode::REVERT) {
+            if let Err(err) = self.gas.record_cost(MIN_OPCODE_COST) {
+                return EvmStatus::Exited(ExitReason::Error(err));
+            }
+        }
+
         match eval(self) {
             ControlFlow::Continue(steps) => {
                 self.pc += steps;
@@ -146,8 +205,9 @@ impl<'a> Machine<'a> {
                                 success: true,
                                 error: None,
                                 logs: self.logs.clone(),
-                                state: self.context.state.clone(),
+                                access_set: self.context.access_set.clone(),
                                 return_val: Some(val),
+                                gas_used: self.gas.used(),
                             }
                         }
                     },
@@ -155,26 +215,32 @@ impl<'a> Machine<'a> {
                         return EvmResult {
                             stack: self.stack(),
                             success: false,
-                            error: Some(error),
                             logs: self.logs.clone(),
-                            state: self.context.state.clone(),
+                            access_set: self.context.access_set.clone(),
                             return_val: match &error {
-                                EvmError::Revert(val) => Some(*val),
+                                EvmError::Revert(val) => Some(val.clone()),
                                 _ => None,
                             },
+                            // exceptional halts consume all gas, reverts only what was used
+                            gas_used: match &error {
+                                EvmError::Revert(_) => self.gas.used(),
+                                _ => self.gas.limit(),
+                            },
+                            error: Some(error),
                         }
                     }
                 },
             }
         }
 
-        return EvmResult {
+        EvmResult {
             stack: self.stack(),
             success: true,
             error: None,
             logs: self.logs.clone(),
-            state: self.context.state.clone(),
+            access_set: self.context.access_set.clone(),
             return_val: None,
-        };
+            gas_used: self.gas.used(),
+        }
     }
 }
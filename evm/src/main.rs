@@ -4,8 +4,9 @@ use evm::{
     context::Context,
     helpers::{hex_decode_with_prefix, Convert},
     state::State,
+    Log,
 };
-use primitive_types::{H160, U256};
+use primitive_types::{H160, H256, U256};
 use serde::Deserialize;
 use std::{collections::HashMap, str::FromStr};
 
@@ -15,7 +16,7 @@ struct Evmtest {
     hint: String,
     code: Code,
     tx: Option<Tx>,
-    block: Option<BlockData>,
+    block: Option<Block>,
     state: Option<StateData>,
     expect: Expect,
 }
@@ -26,19 +27,10 @@ struct Tx {
     from: Option<String>,
     origin: Option<String>,
     gasprice: Option<String>,
+    gas: Option<String>,
     value: Option<String>,
     data: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct BlockData {
-    basefee: Option<String>,
-    coinbase: Option<String>,
-    timestamp: Option<String>,
-    number: Option<String>,
-    difficulty: Option<String>,
-    gaslimit: Option<String>,
-    chainid: Option<String>,
+    blob_versioned_hashes: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,7 +64,7 @@ struct AccountData {
 impl AccountData {
     pub fn hex_decode_balance(&self) -> Vec<u8> {
         match &self.balance {
-            Some(balance) => hex_decode_with_prefix(&balance),
+            Some(balance) => hex_decode_with_prefix(balance),
             None => vec![],
         }
     }
@@ -81,7 +73,7 @@ impl AccountData {
 
         match &self.code {
             Some(code_state) => hex::decode(match &code_state.bin {
-                Some(bin) => &bin,
+                Some(bin) => bin,
                 None => &default,
             })
             .unwrap(),
@@ -111,13 +103,6 @@ struct Expect {
     ret: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Log {
-    address: Option<String>,
-    data: Option<String>,
-    topics: Option<Vec<String>>,
-}
-
 fn main() {
     let text = std::fs::read_to_string("../evm.json").unwrap();
     let data: Vec<Evmtest> = serde_json::from_str(&text).unwrap();
@@ -158,6 +143,13 @@ fn main() {
             },
             None => U256::zero(),
         };
+        let gas_limit = match &test.tx {
+            Some(tx) => match &tx.gas {
+                Some(gas) => gas.to_u256().as_u64(),
+                None => u64::MAX,
+            },
+            None => u64::MAX,
+        };
         let value = match &test.tx {
             Some(tx) => match &tx.value {
                 Some(value) => value.to_u256(),
@@ -173,56 +165,16 @@ fn main() {
             None => String::new(),
         };
 
-        let basefee = match &test.block {
-            Some(tx) => match &tx.basefee {
-                Some(basefee) => hex_decode_with_prefix(basefee),
-                None => vec![],
+        let blob_versioned_hashes: Vec<H256> = match &test.tx {
+            Some(tx) => match &tx.blob_versioned_hashes {
+                Some(hashes) => hashes.iter().map(|hash| hash.to_h256()).collect(),
+                None => Vec::new(),
             },
-            None => vec![],
-        };
-        let coinbase = match &test.block {
-            Some(tx) => match &tx.coinbase {
-                Some(coinbase) => hex_decode_with_prefix(coinbase),
-                None => vec![],
-            },
-            None => vec![],
-        };
-        let timestamp = match &test.block {
-            Some(tx) => match &tx.timestamp {
-                Some(timestamp) => hex_decode_with_prefix(timestamp),
-                None => vec![],
-            },
-            None => vec![],
-        };
-        let number = match &test.block {
-            Some(tx) => match &tx.number {
-                Some(number) => hex_decode_with_prefix(number),
-                None => vec![],
-            },
-            None => vec![],
-        };
-        let difficulty = match &test.block {
-            Some(tx) => match &tx.difficulty {
-                Some(difficulty) => hex_decode_with_prefix(difficulty),
-                None => vec![],
-            },
-            None => vec![],
-        };
-        let gaslimit = match &test.block {
-            Some(tx) => match &tx.gaslimit {
-                Some(gaslimit) => hex_decode_with_prefix(gaslimit),
-                None => vec![],
-            },
-            None => vec![],
-        };
-        let chainid = match &test.block {
-            Some(tx) => match &tx.chainid {
-                Some(chainid) => hex_decode_with_prefix(chainid),
-                None => vec![],
-            },
-            None => vec![],
+            None => Vec::new(),
         };
 
+        let block = test.block.clone().unwrap_or_default();
+
         let account_data_list = match &test.state {
             Some(state) => state.account_data_list(),
             None => StateData::new().account_data_list(),
@@ -232,21 +184,16 @@ fn main() {
 
         state.add_accounts(&account_data_list);
 
+        let mut context = Context::new(
+            address, caller, origin, gasprice, value, &data, &mut state, false,
+        );
+        context.blob_versioned_hashes = blob_versioned_hashes;
+        context.gas_limit = gas_limit;
+
         let result = evm(
             &code,
-            Context::new(
-                address, caller, origin, gasprice, value, &data, state, false,
-            ),
-            Block::new(
-                &coinbase,
-                &timestamp,
-                &number,
-                &difficulty,
-                &gaslimit,
-                &chainid,
-                &basefee,
-            ),
-            None,
+            context,
+            block,
         );
 
         let mut expected_stack: Vec<U256> = Vec::new();
@@ -256,84 +203,32 @@ fn main() {
             }
         }
 
-        let mut matching = result.stack.len() == expected_stack.len();
-        if matching {
-            for i in 0..result.stack.len() {
-                if result.stack[i] != expected_stack[i] {
-                    matching = false;
-                    break;
-                }
-            }
-        }
+        let mut matching = result.stack == expected_stack;
 
-        let mut logs_match = true;
-
-        match &test.expect.logs {
-            Some(logs) => {
-                for (i, log) in logs.iter().enumerate() {
-                    match &log.address {
-                        Some(address) => {
-                            if i >= result.logs.len() {
-                                logs_match = false;
-                            } else {
-                                logs_match = address == &result.logs[i].address;
-                            }
-                        }
-                        None => (),
-                    }
-                    match &log.data {
-                        Some(data) => {
-                            if i >= result.logs.len() {
-                                logs_match = false;
-                            } else {
-                                logs_match = data == &result.logs[i].data
-                            }
-                        }
-                        None => (),
-                    }
-                    match &log.topics {
-                        Some(data) => {
-                            if i >= result.logs.len() {
-                                logs_match = false;
-                            } else {
-                                for (j, topic) in data.iter().enumerate() {
-                                    if j >= result.logs[i].topics.len() {
-                                        logs_match = false
-                                    } else {
-                                        logs_match = topic == &result.logs[i].topics[j]
-                                    }
-                                }
-                            }
-                        }
-                        None => (),
-                    }
-                }
-            }
-            None => (),
-        }
+        let logs_match = match &test.expect.logs {
+            Some(logs) => result.logs == *logs,
+            None => true,
+        };
 
         let mut return_matches = true;
 
-        match &test.expect.ret {
-            Some(ret) => {
-                let expected_ret = U256::from_str_radix(ret, 16).unwrap();
-                let actual_ret = result.return_val;
-                match actual_ret {
-                    Some(actual_ret) => {
-                        return_matches = actual_ret == expected_ret;
-                    }
-                    None => {
-                        return_matches = false;
-                    }
+        if let Some(ret) = &test.expect.ret {
+            let expected_ret = hex_decode_with_prefix(ret);
+            let actual_ret = &result.return_val;
+            match actual_ret {
+                Some(actual_ret) => {
+                    return_matches = *actual_ret == expected_ret;
+                }
+                None => {
+                    return_matches = false;
                 }
             }
-            None => (),
         }
 
         matching = matching
             && result.success == test.expect.success
             && (test.expect.success && result.error.is_none()
-                || !test.expect.success && !result.error.is_none())
+                || !test.expect.success && result.error.is_some())
             && logs_match
             && return_matches;
 
@@ -358,32 +253,7 @@ fn main() {
             println!("]");
             match &test.expect.logs {
                 Some(logs) => {
-                    println!("Expected logs: [");
-                    for log in logs {
-                        match &log.address {
-                            Some(addr) => println!("  address: {:?},", addr),
-                            None => (),
-                        }
-                        match &log.data {
-                            Some(data) => println!("  data: {:?},", data),
-                            None => (),
-                        }
-                        match &log.topics {
-                            Some(topics) => {
-                                if topics.len() > 0 {
-                                    println!("  topics: [");
-                                    for topic in topics {
-                                        println!("    {:?}", topic);
-                                    }
-                                    println!("  ]");
-                                } else {
-                                    println!("  topics: {:?},", topics);
-                                }
-                            }
-                            None => (),
-                        }
-                    }
-                    println!("]\n");
+                    println!("Expected logs: {}\n", serde_json::to_string_pretty(logs).unwrap());
                 }
                 None => {
                     println!("\n");
@@ -392,28 +262,14 @@ fn main() {
 
             println!("Actual error: {:?}", result.error);
             println!("Actual success: {:?}", result.success);
-            println!("Actual return: {:?}", result.return_val);
+            println!("Actual return: {:?}", result.return_val.as_ref().map(hex::encode));
             println!("Actual stack: [");
             for v in result.stack {
                 println!("  {:#X},", v);
             }
             println!("]");
-            if result.logs.len() > 0 {
-                println!("Actual logs: [");
-                for log in result.logs {
-                    println!("  address: {:?},", log.address);
-                    println!("  data: {:?},", log.data);
-                    if log.topics.len() > 0 {
-                        println!("  topics: [");
-                        for topic in log.topics {
-                            println!("    {:?}", topic);
-                        }
-                        println!("  ]");
-                    } else {
-                        println!("  topics: []")
-                    }
-                }
-                println!("]\n");
+            if !result.logs.is_empty() {
+                println!("Actual logs: {}\n", serde_json::to_string_pretty(&result.logs).unwrap());
             } else {
                 println!("\n")
             }
@@ -19,9 +19,9 @@ impl Memory {
     }
 
     fn resize(&mut self, length: usize) {
-        if length >= self.len_words * WORD_BYTES {
-            self.data.resize(length, 0);
+        if length > self.data.len() {
             self.len_words = ceil_divide(length, WORD_BYTES);
+            self.data.resize(self.len_words * WORD_BYTES, 0);
         }
     }
 
@@ -36,6 +36,11 @@ impl Memory {
         }
     }
 
+    pub fn set_bytes(&mut self, byte_offset: usize, data: &[u8]) {
+        self.resize(byte_offset + data.len());
+        self.data[byte_offset..byte_offset + data.len()].copy_from_slice(data);
+    }
+
     pub fn get(&mut self, byte_offset: usize, target_size: usize) -> &[u8] {
         let end_index = byte_offset + target_size;
         self.resize(end_index);
@@ -53,6 +53,8 @@ impl Opcode {
     pub const CHAINID: u8 = 0x46;
     pub const SELFBALANCE: u8 = 0x47;
     pub const BASEFEE: u8 = 0x48;
+    pub const BLOBHASH: u8 = 0x49;
+    pub const BLOBBASEFEE: u8 = 0x4a;
     pub const POP: u8 = 0x50;
     pub const MLOAD: u8 = 0x51;
     pub const MSTORE: u8 = 0x52;
@@ -65,6 +67,7 @@ impl Opcode {
     pub const MSIZE: u8 = 0x59;
     pub const GAS: u8 = 0x5a;
     pub const JUMPDEST: u8 = 0x5b;
+    pub const PUSH0: u8 = 0x5f;
     pub const PUSH1: u8 = 0x60;
     pub const PUSH32: u8 = 0x7f;
     pub const DUP1: u8 = 0x80;
@@ -85,15 +88,15 @@ impl Opcode {
 }
 
 impl Opcode {
+    // CALL is only disallowed in a static context when it transfers value, see `call`
     pub fn is_static(value: u8) -> bool {
-        match value {
-            Opcode::CREATE => false,
-            Opcode::CREATE2 => false,
-            Opcode::LOG0..=Opcode::LOG4 => false,
-            Opcode::SSTORE => false,
-            Opcode::SELFDESTRUCT => false,
-            Opcode::CALL => false,
-            _ => true,
-        }
+        !matches!(
+            value,
+            Opcode::CREATE
+                | Opcode::CREATE2
+                | Opcode::LOG0..=Opcode::LOG4
+                | Opcode::SSTORE
+                | Opcode::SELFDESTRUCT
+        )
     }
 }
@@ -0,0 +1,129 @@
+use super::{msm_cost, read_fp, read_scalar, write_fp, PADDED_FP_LEN, SCALAR_BITS, SCALAR_LEN};
+use crate::machine::EvmError;
+use crate::precompiles::{check_gas, PrecompileOutput, PrecompileResult};
+use blst::{
+    blst_map_to_g1, blst_p1, blst_p1_add_or_double, blst_p1_add_or_double_affine, blst_p1_affine,
+    blst_p1_affine_in_g1, blst_p1_affine_on_curve, blst_p1_from_affine, blst_p1_mult,
+    blst_p1_to_affine,
+};
+
+const G1_ADD_COST: u64 = 375;
+const G1_MUL_COST: u64 = 12000;
+const MAP_FP_TO_G1_COST: u64 = 5500;
+
+const G1_MSM_DISCOUNT: [u64; 128] = [
+    1000, 949, 848, 797, 764, 750, 738, 728, 719, 712, 705, 698, 692, 687, 682, 677, 673, 669, 665,
+    661, 658, 654, 651, 648, 645, 642, 640, 637, 635, 632, 630, 627, 625, 623, 621, 619, 617, 615,
+    613, 611, 609, 608, 606, 604, 603, 601, 599, 598, 596, 595, 593, 592, 591, 589, 588, 586, 585,
+    584, 582, 581, 580, 579, 577, 576, 575, 574, 573, 572, 570, 569, 568, 567, 566, 565, 564, 563,
+    562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 551, 550, 549, 548, 547, 547, 546, 545,
+    544, 543, 542, 541, 540, 540, 539, 538, 537, 536, 536, 535, 534, 533, 532, 532, 531, 530, 529,
+    528, 528, 527, 526, 525, 525, 524, 523, 522, 522, 521, 520, 520, 519,
+];
+
+pub(super) const G1_LEN: usize = 2 * PADDED_FP_LEN;
+const G1_MSM_PAIR_LEN: usize = G1_LEN + SCALAR_LEN;
+
+// input = a ++ b
+pub fn g1_add(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = G1_ADD_COST;
+    check_gas(gas_used, gas_limit)?;
+
+    if input.len() != 2 * G1_LEN {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    // addition does not require the points to be in the subgroup
+    let a = read_g1(&input[..G1_LEN], false)?;
+    let b = read_g1(&input[G1_LEN..], false)?;
+
+    let mut a_projective = blst_p1::default();
+    let mut sum = blst_p1::default();
+    unsafe {
+        blst_p1_from_affine(&mut a_projective, &a);
+        blst_p1_add_or_double_affine(&mut sum, &a_projective, &b);
+    }
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g1(&sum),
+    })
+}
+
+// input = (point ++ scalar) * k
+pub fn g1_msm(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    if input.is_empty() || !input.len().is_multiple_of(G1_MSM_PAIR_LEN) {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let k = input.len() / G1_MSM_PAIR_LEN;
+    let gas_used = msm_cost(k, G1_MUL_COST, &G1_MSM_DISCOUNT);
+    check_gas(gas_used, gas_limit)?;
+
+    let mut acc = blst_p1::default();
+    for pair in input.chunks(G1_MSM_PAIR_LEN) {
+        let point = read_g1(&pair[..G1_LEN], true)?;
+        let scalar = read_scalar(&pair[G1_LEN..]);
+
+        let mut projective = blst_p1::default();
+        let mut product = blst_p1::default();
+        let mut sum = blst_p1::default();
+        unsafe {
+            blst_p1_from_affine(&mut projective, &point);
+            blst_p1_mult(&mut product, &projective, scalar.b.as_ptr(), SCALAR_BITS);
+            blst_p1_add_or_double(&mut sum, &acc, &product);
+        }
+        acc = sum;
+    }
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g1(&acc),
+    })
+}
+
+// input = a field element, output = the point it maps to with the cofactor cleared
+pub fn map_fp_to_g1(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = MAP_FP_TO_G1_COST;
+    check_gas(gas_used, gas_limit)?;
+
+    if input.len() != PADDED_FP_LEN {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let fp = read_fp(input)?;
+    let mut point = blst_p1::default();
+    unsafe { blst_map_to_g1(&mut point, &fp, std::ptr::null()) };
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g1(&point),
+    })
+}
+
+// input = x ++ y, all zeros encodes the point at infinity
+pub(super) fn read_g1(input: &[u8], subgroup_check: bool) -> Result<blst_p1_affine, EvmError> {
+    let point = blst_p1_affine {
+        x: read_fp(&input[..PADDED_FP_LEN])?,
+        y: read_fp(&input[PADDED_FP_LEN..])?,
+    };
+
+    if !unsafe { blst_p1_affine_on_curve(&point) } {
+        return Err(EvmError::PrecompileFailure);
+    }
+    if subgroup_check && !unsafe { blst_p1_affine_in_g1(&point) } {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    Ok(point)
+}
+
+fn encode_g1(point: &blst_p1) -> Vec<u8> {
+    let mut affine = blst_p1_affine::default();
+    unsafe { blst_p1_to_affine(&mut affine, point) };
+
+    let mut output = vec![0; G1_LEN];
+    write_fp(&mut output[..PADDED_FP_LEN], &affine.x);
+    write_fp(&mut output[PADDED_FP_LEN..], &affine.y);
+    output
+}
@@ -0,0 +1,141 @@
+use super::{msm_cost, read_fp, read_scalar, write_fp, PADDED_FP_LEN, SCALAR_BITS, SCALAR_LEN};
+use crate::machine::EvmError;
+use crate::precompiles::{check_gas, PrecompileOutput, PrecompileResult};
+use blst::{
+    blst_fp2, blst_map_to_g2, blst_p2, blst_p2_add_or_double, blst_p2_add_or_double_affine,
+    blst_p2_affine, blst_p2_affine_in_g2, blst_p2_affine_on_curve, blst_p2_from_affine,
+    blst_p2_mult, blst_p2_to_affine,
+};
+
+const G2_ADD_COST: u64 = 600;
+const G2_MUL_COST: u64 = 22500;
+const MAP_FP2_TO_G2_COST: u64 = 23800;
+
+const G2_MSM_DISCOUNT: [u64; 128] = [
+    1000, 1000, 923, 884, 855, 832, 812, 796, 782, 770, 759, 749, 740, 732, 724, 717, 711, 704,
+    699, 693, 688, 683, 679, 674, 670, 666, 663, 659, 655, 652, 649, 646, 643, 640, 637, 634, 632,
+    629, 627, 624, 622, 620, 618, 615, 613, 611, 609, 607, 606, 604, 602, 600, 598, 597, 595, 593,
+    592, 590, 589, 587, 586, 584, 583, 582, 580, 579, 578, 576, 575, 574, 573, 571, 570, 569, 568,
+    567, 566, 565, 563, 562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 552, 551, 550, 549,
+    548, 547, 546, 545, 545, 544, 543, 542, 541, 541, 540, 539, 538, 537, 537, 536, 535, 535, 534,
+    533, 532, 532, 531, 530, 530, 529, 528, 528, 527, 526, 526, 525, 524, 524,
+];
+
+const FP2_LEN: usize = 2 * PADDED_FP_LEN;
+pub(super) const G2_LEN: usize = 2 * FP2_LEN;
+const G2_MSM_PAIR_LEN: usize = G2_LEN + SCALAR_LEN;
+
+// input = a ++ b
+pub fn g2_add(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = G2_ADD_COST;
+    check_gas(gas_used, gas_limit)?;
+
+    if input.len() != 2 * G2_LEN {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    // addition does not require the points to be in the subgroup
+    let a = read_g2(&input[..G2_LEN], false)?;
+    let b = read_g2(&input[G2_LEN..], false)?;
+
+    let mut a_projective = blst_p2::default();
+    let mut sum = blst_p2::default();
+    unsafe {
+        blst_p2_from_affine(&mut a_projective, &a);
+        blst_p2_add_or_double_affine(&mut sum, &a_projective, &b);
+    }
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g2(&sum),
+    })
+}
+
+// input = (point ++ scalar) * k
+pub fn g2_msm(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    if input.is_empty() || !input.len().is_multiple_of(G2_MSM_PAIR_LEN) {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let k = input.len() / G2_MSM_PAIR_LEN;
+    let gas_used = msm_cost(k, G2_MUL_COST, &G2_MSM_DISCOUNT);
+    check_gas(gas_used, gas_limit)?;
+
+    let mut acc = blst_p2::default();
+    for pair in input.chunks(G2_MSM_PAIR_LEN) {
+        let point = read_g2(&pair[..G2_LEN], true)?;
+        let scalar = read_scalar(&pair[G2_LEN..]);
+
+        let mut projective = blst_p2::default();
+        let mut product = blst_p2::default();
+        let mut sum = blst_p2::default();
+        unsafe {
+            blst_p2_from_affine(&mut projective, &point);
+            blst_p2_mult(&mut product, &projective, scalar.b.as_ptr(), SCALAR_BITS);
+            blst_p2_add_or_double(&mut sum, &acc, &product);
+        }
+        acc = sum;
+    }
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g2(&acc),
+    })
+}
+
+// input = an Fp2 element, output = the point it maps to with the cofactor cleared
+pub fn map_fp2_to_g2(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = MAP_FP2_TO_G2_COST;
+    check_gas(gas_used, gas_limit)?;
+
+    if input.len() != FP2_LEN {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let fp2 = read_fp2(input)?;
+    let mut point = blst_p2::default();
+    unsafe { blst_map_to_g2(&mut point, &fp2, std::ptr::null()) };
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g2(&point),
+    })
+}
+
+// input = x ++ y, all zeros encodes the point at infinity
+pub(super) fn read_g2(input: &[u8], subgroup_check: bool) -> Result<blst_p2_affine, EvmError> {
+    let point = blst_p2_affine {
+        x: read_fp2(&input[..FP2_LEN])?,
+        y: read_fp2(&input[FP2_LEN..])?,
+    };
+
+    if !unsafe { blst_p2_affine_on_curve(&point) } {
+        return Err(EvmError::PrecompileFailure);
+    }
+    if subgroup_check && !unsafe { blst_p2_affine_in_g2(&point) } {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    Ok(point)
+}
+
+// Fp2 elements are encoded as c0 ++ c1
+fn read_fp2(input: &[u8]) -> Result<blst_fp2, EvmError> {
+    Ok(blst_fp2 {
+        fp: [
+            read_fp(&input[..PADDED_FP_LEN])?,
+            read_fp(&input[PADDED_FP_LEN..])?,
+        ],
+    })
+}
+
+fn encode_g2(point: &blst_p2) -> Vec<u8> {
+    let mut affine = blst_p2_affine::default();
+    unsafe { blst_p2_to_affine(&mut affine, point) };
+
+    let mut output = vec![0; G2_LEN];
+    for (i, fp) in [affine.x.fp, affine.y.fp].iter().flatten().enumerate() {
+        write_fp(&mut output[i * PADDED_FP_LEN..(i + 1) * PADDED_FP_LEN], fp);
+    }
+    output
+}
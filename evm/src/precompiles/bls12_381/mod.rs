@@ -0,0 +1,62 @@
+// EIP-2537 precompiles for BLS12-381 curve operations
+mod g1;
+mod g2;
+mod pairing;
+
+pub use g1::{g1_add, g1_msm, map_fp_to_g1};
+pub use g2::{g2_add, g2_msm, map_fp2_to_g2};
+pub use pairing::pairing;
+
+use std::cmp::Ordering;
+
+use crate::machine::EvmError;
+use blst::{
+    blst_bendian_from_fp, blst_fp, blst_fp_from_bendian, blst_scalar, blst_scalar_from_bendian,
+};
+
+const FP_LEN: usize = 48;
+// Field elements are encoded as 64 bytes, the top 16 of which must be zero
+const PADDED_FP_LEN: usize = 64;
+const SCALAR_LEN: usize = 32;
+const SCALAR_BITS: usize = 256;
+
+const MODULUS: [u8; FP_LEN] = [
+    0x1a, 0x01, 0x11, 0xea, 0x39, 0x7f, 0xe6, 0x9a, 0x4b, 0x1b, 0xa7, 0xb6, 0x43, 0x4b, 0xac, 0xd7,
+    0x64, 0x77, 0x4b, 0x84, 0xf3, 0x85, 0x12, 0xbf, 0x67, 0x30, 0xd2, 0xa0, 0xf6, 0xb0, 0xf6, 0x24,
+    0x1e, 0xab, 0xff, 0xfe, 0xb1, 0x53, 0xff, 0xff, 0xb9, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xaa, 0xab,
+];
+
+const MSM_MULTIPLIER: u64 = 1000;
+
+fn read_fp(input: &[u8]) -> Result<blst_fp, EvmError> {
+    let (padding, value) = input.split_at(PADDED_FP_LEN - FP_LEN);
+    if padding.iter().any(|&byte| byte != 0) {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    // values must be canonical, i.e. less than the modulus
+    if value.cmp(&MODULUS[..]) != Ordering::Less {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let mut fp = blst_fp::default();
+    unsafe { blst_fp_from_bendian(&mut fp, value.as_ptr()) };
+    Ok(fp)
+}
+
+fn write_fp(output: &mut [u8], fp: &blst_fp) {
+    unsafe { blst_bendian_from_fp(output[PADDED_FP_LEN - FP_LEN..].as_mut_ptr(), fp) };
+}
+
+// Scalars are big endian and are not required to be less than the group order
+fn read_scalar(input: &[u8]) -> blst_scalar {
+    let mut scalar = blst_scalar::default();
+    unsafe { blst_scalar_from_bendian(&mut scalar, input.as_ptr()) };
+    scalar
+}
+
+// k * multiplication_cost * discount(k) / 1000, the discount is capped at the last entry of the table
+fn msm_cost(k: usize, multiplication_cost: u64, discount_table: &[u64]) -> u64 {
+    let discount = discount_table[k.min(discount_table.len()) - 1];
+    k as u64 * multiplication_cost * discount / MSM_MULTIPLIER
+}
@@ -0,0 +1,51 @@
+use super::g1::{read_g1, G1_LEN};
+use super::g2::{read_g2, G2_LEN};
+use crate::machine::EvmError;
+use crate::precompiles::{check_gas, PrecompileOutput, PrecompileResult};
+use blst::{
+    blst_final_exp, blst_fp12, blst_fp12_is_one, blst_fp12_mul, blst_fp12_one, blst_miller_loop,
+    blst_p1_affine_is_inf, blst_p2_affine_is_inf,
+};
+
+const PAIRING_BASE_COST: u64 = 37700;
+const PAIRING_PER_PAIR_COST: u64 = 32600;
+
+const PAIR_LEN: usize = G1_LEN + G2_LEN;
+
+// input = (G1 point ++ G2 point) * k, output = 1 if the product of the pairings is one, else 0
+pub fn pairing(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    if input.is_empty() || !input.len().is_multiple_of(PAIR_LEN) {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let k = input.len() / PAIR_LEN;
+    let gas_used = PAIRING_BASE_COST + PAIRING_PER_PAIR_COST * k as u64;
+    check_gas(gas_used, gas_limit)?;
+
+    let mut acc = unsafe { *blst_fp12_one() };
+    for pair in input.chunks(PAIR_LEN) {
+        let a = read_g1(&pair[..G1_LEN], true)?;
+        let b = read_g2(&pair[G1_LEN..], true)?;
+
+        // pairings with the point at infinity are one, so do not change the product
+        if unsafe { blst_p1_affine_is_inf(&a) || blst_p2_affine_is_inf(&b) } {
+            continue;
+        }
+
+        let mut miller_loop = blst_fp12::default();
+        let mut product = blst_fp12::default();
+        unsafe {
+            blst_miller_loop(&mut miller_loop, &b, &a);
+            blst_fp12_mul(&mut product, &acc, &miller_loop);
+        }
+        acc = product;
+    }
+
+    let mut result = blst_fp12::default();
+    unsafe { blst_final_exp(&mut result, &acc) };
+
+    let mut output = vec![0; 32];
+    output[31] = unsafe { blst_fp12_is_one(&result) } as u8;
+
+    Ok(PrecompileOutput { gas_used, output })
+}
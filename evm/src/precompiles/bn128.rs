@@ -0,0 +1,153 @@
+use super::{check_gas, padded_input, PrecompileOutput, PrecompileResult};
+use crate::machine::EvmError;
+use substrate_bn::{pairing_batch, AffineG1, AffineG2, Fq, Fq2, Fr, Group, Gt, G1, G2};
+
+// EIP-196 and EIP-197
+const BYZANTIUM_ADD_COST: u64 = 500;
+const BYZANTIUM_MUL_COST: u64 = 40000;
+const BYZANTIUM_PAIRING_BASE_COST: u64 = 100000;
+const BYZANTIUM_PAIRING_PER_POINT_COST: u64 = 80000;
+// EIP-1108
+const ISTANBUL_ADD_COST: u64 = 150;
+const ISTANBUL_MUL_COST: u64 = 6000;
+const ISTANBUL_PAIRING_BASE_COST: u64 = 45000;
+const ISTANBUL_PAIRING_PER_POINT_COST: u64 = 34000;
+
+const G1_LEN: usize = 64;
+const G2_LEN: usize = 128;
+const PAIR_LEN: usize = G1_LEN + G2_LEN;
+
+pub fn byzantium_add(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    add(input, gas_limit, BYZANTIUM_ADD_COST)
+}
+
+pub fn istanbul_add(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    add(input, gas_limit, ISTANBUL_ADD_COST)
+}
+
+pub fn byzantium_mul(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    mul(input, gas_limit, BYZANTIUM_MUL_COST)
+}
+
+pub fn istanbul_mul(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    mul(input, gas_limit, ISTANBUL_MUL_COST)
+}
+
+pub fn byzantium_pairing(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    pairing(
+        input,
+        gas_limit,
+        BYZANTIUM_PAIRING_BASE_COST,
+        BYZANTIUM_PAIRING_PER_POINT_COST,
+    )
+}
+
+pub fn istanbul_pairing(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    pairing(
+        input,
+        gas_limit,
+        ISTANBUL_PAIRING_BASE_COST,
+        ISTANBUL_PAIRING_PER_POINT_COST,
+    )
+}
+
+// input = x1 ++ y1 ++ x2 ++ y2, zero padded
+fn add(input: &[u8], gas_limit: u64, gas_used: u64) -> PrecompileResult {
+    check_gas(gas_used, gas_limit)?;
+
+    let input = padded_input(input, 0, 2 * G1_LEN);
+    let a = read_g1(&input[..G1_LEN])?;
+    let b = read_g1(&input[G1_LEN..])?;
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g1(a + b),
+    })
+}
+
+// input = x ++ y ++ scalar, zero padded
+fn mul(input: &[u8], gas_limit: u64, gas_used: u64) -> PrecompileResult {
+    check_gas(gas_used, gas_limit)?;
+
+    let input = padded_input(input, 0, G1_LEN + 32);
+    let point = read_g1(&input[..G1_LEN])?;
+    // the scalar is reduced modulo the group order
+    let scalar = Fr::from_slice(&input[G1_LEN..]).map_err(|_| EvmError::PrecompileFailure)?;
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: encode_g1(point * scalar),
+    })
+}
+
+// input = (G1 point ++ G2 point) * k, output = 1 if the product of the pairings is one, else 0
+fn pairing(input: &[u8], gas_limit: u64, base_cost: u64, per_point_cost: u64) -> PrecompileResult {
+    let gas_used = base_cost + per_point_cost * (input.len() / PAIR_LEN) as u64;
+    check_gas(gas_used, gas_limit)?;
+
+    if !input.len().is_multiple_of(PAIR_LEN) {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let mut pairs = Vec::new();
+    for pair in input.chunks(PAIR_LEN) {
+        let a = read_g1(&pair[..G1_LEN])?;
+        let b = read_g2(&pair[G1_LEN..])?;
+
+        // pairings with the point at infinity are one, so do not change the product
+        if !a.is_zero() && !b.is_zero() {
+            pairs.push((a, b));
+        }
+    }
+
+    let success = pairs.is_empty() || pairing_batch(&pairs) == Gt::one();
+
+    let mut output = vec![0; 32];
+    output[31] = success as u8;
+
+    Ok(PrecompileOutput { gas_used, output })
+}
+
+fn read_fq(input: &[u8]) -> Result<Fq, EvmError> {
+    Fq::from_slice(input).map_err(|_| EvmError::PrecompileFailure)
+}
+
+// (0, 0) encodes the point at infinity, any other point must be on the curve
+fn read_g1(input: &[u8]) -> Result<G1, EvmError> {
+    let x = read_fq(&input[0..32])?;
+    let y = read_fq(&input[32..64])?;
+
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1::zero());
+    }
+
+    AffineG1::new(x, y)
+        .map(G1::from)
+        .map_err(|_| EvmError::PrecompileFailure)
+}
+
+// Fq2 elements are encoded with the imaginary part first, points must also be in the correct subgroup
+fn read_g2(input: &[u8]) -> Result<G2, EvmError> {
+    let x = Fq2::new(read_fq(&input[32..64])?, read_fq(&input[0..32])?);
+    let y = Fq2::new(read_fq(&input[96..128])?, read_fq(&input[64..96])?);
+
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2::zero());
+    }
+
+    AffineG2::new(x, y)
+        .map(G2::from)
+        .map_err(|_| EvmError::PrecompileFailure)
+}
+
+fn encode_g1(point: G1) -> Vec<u8> {
+    let mut output = vec![0; G1_LEN];
+
+    // the point at infinity has no affine form and is encoded as (0, 0)
+    if let Some(point) = AffineG1::from_jacobian(point) {
+        point.x().to_big_endian(&mut output[..32]).unwrap();
+        point.y().to_big_endian(&mut output[32..]).unwrap();
+    }
+
+    output
+}
@@ -0,0 +1,114 @@
+use super::{check_gas, padded_input, PrecompileOutput, PrecompileResult};
+use crate::helpers::Convert;
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use primitive_types::{H160, U256};
+use sha3::{Digest, Keccak256};
+
+const ECRECOVER_COST: u64 = 3000;
+
+// input = hash (32 bytes) ++ v (32 bytes) ++ r (32 bytes) ++ s (32 bytes), zero padded
+// an invalid signature is not an error, the output is just empty
+pub fn run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    check_gas(ECRECOVER_COST, gas_limit)?;
+
+    let data = padded_input(input, 0, 128);
+
+    let output = match recover(&data) {
+        Some(address) => address.to_h256().as_bytes().to_vec(),
+        None => Vec::new(),
+    };
+
+    Ok(PrecompileOutput {
+        gas_used: ECRECOVER_COST,
+        output,
+    })
+}
+
+fn recover(data: &[u8]) -> Option<H160> {
+    let v = U256::from_big_endian(&data[32..64]);
+    if v != 27.into() && v != 28.into() {
+        return None;
+    }
+
+    recover_address(&data[..32], v.low_u32() as u8 - 27, &data[64..128])
+}
+
+// signature = r (32 bytes) ++ s (32 bytes), also used for EIP-7702 authorizations
+pub(crate) fn recover_address(hash: &[u8], recovery_id: u8, signature: &[u8]) -> Option<H160> {
+    let mut recovery_id = RecoveryId::from_byte(recovery_id)?;
+    let mut signature = Signature::from_slice(signature).ok()?;
+
+    // ecrecover accepts high s values, which not every k256 version recovers from. The low s equivalent
+    // recovers the same key with the opposite y parity
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+        recovery_id = RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced());
+    }
+
+    let key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id).ok()?;
+
+    // the address is the last 20 bytes of the hash of the uncompressed public key, without its 0x04 prefix
+    let public_key = key.to_encoded_point(false);
+    let hash = Keccak256::digest(&public_key.as_bytes()[1..]);
+
+    Some(H160::from_slice(&hash[12..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    fn sign(hash: &[u8]) -> (H160, Signature, RecoveryId) {
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let (signature, recovery_id) = key.sign_prehash_recoverable(hash).unwrap();
+
+        let public_key = key.verifying_key().to_encoded_point(false);
+        let address = H160::from_slice(&Keccak256::digest(&public_key.as_bytes()[1..])[12..]);
+        (address, signature, recovery_id)
+    }
+
+    fn input(hash: &[u8], v: u8, signature: &Signature) -> Vec<u8> {
+        let mut input = hash.to_vec();
+        input.extend_from_slice(&U256::from(v).to_h256().0);
+        input.extend_from_slice(&signature.to_bytes());
+        input
+    }
+
+    #[test]
+    fn recovers_the_signer() {
+        let hash = Keccak256::digest(b"message");
+        let (address, signature, recovery_id) = sign(&hash);
+
+        let res = run(&input(&hash, 27 + recovery_id.to_byte(), &signature), 3000).unwrap();
+
+        assert_eq!(res.gas_used, 3000);
+        assert_eq!(res.output, address.to_h256().as_bytes());
+    }
+
+    #[test]
+    fn recovers_the_signer_from_a_high_s_signature() {
+        let hash = Keccak256::digest(b"message");
+        let (address, signature, recovery_id) = sign(&hash);
+        assert!(signature.normalize_s().is_none());
+
+        // (r, n - s) with the opposite parity is the same signature
+        let (r, s) = signature.split_scalars();
+        let high_s = Signature::from_scalars(r, -*s).unwrap();
+        let v = 27 + (recovery_id.to_byte() ^ 1);
+
+        let res = run(&input(&hash, v, &high_s), 3000).unwrap();
+
+        assert_eq!(res.output, address.to_h256().as_bytes());
+    }
+
+    #[test]
+    fn invalid_v_returns_nothing() {
+        let hash = Keccak256::digest(b"message");
+        let (_, signature, _) = sign(&hash);
+
+        let res = run(&input(&hash, 29, &signature), 3000).unwrap();
+
+        assert!(res.output.is_empty());
+    }
+}
@@ -0,0 +1,14 @@
+use super::{check_gas, linear_cost, PrecompileOutput, PrecompileResult};
+
+const IDENTITY_BASE_COST: u64 = 15;
+const IDENTITY_WORD_COST: u64 = 3;
+
+pub fn run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = linear_cost(input.len(), IDENTITY_BASE_COST, IDENTITY_WORD_COST);
+    check_gas(gas_used, gas_limit)?;
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: input.to_vec(),
+    })
+}
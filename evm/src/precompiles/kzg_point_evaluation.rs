@@ -0,0 +1,72 @@
+use std::sync::OnceLock;
+
+use super::{check_gas, PrecompileOutput, PrecompileResult};
+use crate::consts::VERSIONED_HASH_VERSION_KZG;
+use crate::machine::EvmError;
+use c_kzg::{Bytes32, Bytes48, KzgProof, KzgSettings, FIELD_ELEMENTS_PER_BLOB};
+use sha2::{Digest, Sha256};
+
+// EIP-4844
+const POINT_EVALUATION_COST: u64 = 50000;
+const BLS_MODULUS: [u8; 32] = [
+    0x73, 0xed, 0xa7, 0x53, 0x29, 0x9d, 0x7d, 0x48, 0x33, 0x39, 0xd8, 0x08, 0x09, 0xa1, 0xd8, 0x05,
+    0x53, 0xbd, 0xa4, 0x02, 0xff, 0xfe, 0x5b, 0xfe, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01,
+];
+
+// input = versioned_hash (32 bytes) ++ z (32 bytes) ++ y (32 bytes) ++ commitment (48 bytes) ++ proof (48 bytes)
+const INPUT_LEN: usize = 192;
+
+// The setup from the KZG ceremony, parsed the first time it is needed
+static TRUSTED_SETUP: OnceLock<KzgSettings> = OnceLock::new();
+
+fn trusted_setup() -> &'static KzgSettings {
+    TRUSTED_SETUP.get_or_init(|| {
+        KzgSettings::parse_kzg_trusted_setup(include_str!("trusted_setup.txt"))
+            .expect("bundled trusted setup is valid")
+    })
+}
+
+pub fn run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = POINT_EVALUATION_COST;
+    check_gas(gas_used, gas_limit)?;
+
+    if input.len() != INPUT_LEN {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let versioned_hash = &input[0..32];
+    let commitment = &input[96..144];
+    if versioned_hash != kzg_to_versioned_hash(commitment) {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    let to_bytes32 = |bytes| Bytes32::from_bytes(bytes).map_err(|_| EvmError::PrecompileFailure);
+    let to_bytes48 = |bytes| Bytes48::from_bytes(bytes).map_err(|_| EvmError::PrecompileFailure);
+
+    let valid = KzgProof::verify_kzg_proof(
+        &to_bytes48(commitment)?,
+        &to_bytes32(&input[32..64])?,
+        &to_bytes32(&input[64..96])?,
+        &to_bytes48(&input[144..192])?,
+        trusted_setup(),
+    )
+    .map_err(|_| EvmError::PrecompileFailure)?;
+
+    if !valid {
+        return Err(EvmError::PrecompileFailure);
+    }
+
+    // output = FIELD_ELEMENTS_PER_BLOB ++ BLS_MODULUS, both as 32 byte big endian words
+    let mut output = vec![0; 64];
+    output[24..32].copy_from_slice(&(FIELD_ELEMENTS_PER_BLOB as u64).to_be_bytes());
+    output[32..].copy_from_slice(&BLS_MODULUS);
+
+    Ok(PrecompileOutput { gas_used, output })
+}
+
+// versioned_hash = VERSIONED_HASH_VERSION_KZG ++ sha256(commitment)[1..]
+fn kzg_to_versioned_hash(commitment: &[u8]) -> [u8; 32] {
+    let mut hash: [u8; 32] = Sha256::digest(commitment).into();
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    hash
+}
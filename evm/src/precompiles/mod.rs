@@ -0,0 +1,87 @@
+mod blake2f;
+mod bls12_381;
+mod bn128;
+pub(crate) mod ecrecover;
+mod identity;
+mod kzg_point_evaluation;
+mod modexp;
+mod native;
+#[cfg(feature = "p256verify")]
+mod p256verify;
+mod ripemd160;
+mod sha256;
+
+pub use native::{NativeOutput, NativePrecompile, NativePrecompiles};
+
+use crate::consts::WORD_BYTES;
+use crate::fork::Fork;
+use crate::helpers::ceil_divide;
+use crate::machine::EvmError;
+use primitive_types::H160;
+
+pub struct PrecompileOutput {
+    pub gas_used: u64,
+    pub output: Vec<u8>,
+}
+
+// An error fails the call and consumes all of the gas given to it
+pub type PrecompileResult = Result<PrecompileOutput, EvmError>;
+
+pub type Precompile = fn(&[u8], u64) -> PrecompileResult;
+
+pub fn get_precompile(address: H160, fork: Fork) -> Option<Precompile> {
+    let index = address.to_low_u64_be();
+    if H160::from_low_u64_be(index) != address {
+        return None;
+    }
+
+    match index {
+        0x01 => Some(ecrecover::run),
+        0x02 => Some(sha256::run),
+        0x03 => Some(ripemd160::run),
+        0x04 => Some(identity::run),
+        0x05 if fork.is_enabled(Fork::Berlin) => Some(modexp::berlin_run),
+        0x05 => Some(modexp::byzantium_run),
+        0x06 if fork.is_enabled(Fork::Istanbul) => Some(bn128::istanbul_add),
+        0x06 => Some(bn128::byzantium_add),
+        0x07 if fork.is_enabled(Fork::Istanbul) => Some(bn128::istanbul_mul),
+        0x07 => Some(bn128::byzantium_mul),
+        0x08 if fork.is_enabled(Fork::Istanbul) => Some(bn128::istanbul_pairing),
+        0x08 => Some(bn128::byzantium_pairing),
+        0x09 if fork.is_enabled(Fork::Istanbul) => Some(blake2f::run),
+        0x0a if fork.is_enabled(Fork::Cancun) => Some(kzg_point_evaluation::run),
+        0x0b if fork.is_enabled(Fork::Prague) => Some(bls12_381::g1_add),
+        0x0c if fork.is_enabled(Fork::Prague) => Some(bls12_381::g1_msm),
+        0x0d if fork.is_enabled(Fork::Prague) => Some(bls12_381::g2_add),
+        0x0e if fork.is_enabled(Fork::Prague) => Some(bls12_381::g2_msm),
+        0x0f if fork.is_enabled(Fork::Prague) => Some(bls12_381::pairing),
+        0x10 if fork.is_enabled(Fork::Prague) => Some(bls12_381::map_fp_to_g1),
+        0x11 if fork.is_enabled(Fork::Prague) => Some(bls12_381::map_fp2_to_g2),
+        #[cfg(feature = "p256verify")]
+        0x100 => Some(p256verify::run),
+        _ => None,
+    }
+}
+
+// base + word * ceil(len / 32)
+fn linear_cost(input_len: usize, base: u64, word: u64) -> u64 {
+    base + word * ceil_divide(input_len, WORD_BYTES) as u64
+}
+
+// Reads len bytes of input from offset, zero padded past the end of the input
+fn padded_input(input: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut res = vec![0; len];
+    if offset < input.len() {
+        let end = input.len().min(offset.saturating_add(len));
+        res[..end - offset].copy_from_slice(&input[offset..end]);
+    }
+    res
+}
+
+fn check_gas(gas_used: u64, gas_limit: u64) -> Result<(), EvmError> {
+    if gas_used > gas_limit {
+        Err(EvmError::OutOfGas)
+    } else {
+        Ok(())
+    }
+}
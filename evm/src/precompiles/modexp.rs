@@ -0,0 +1,191 @@
+use super::{padded_input, PrecompileOutput, PrecompileResult};
+use crate::helpers::add_padding;
+use crate::machine::EvmError;
+use num_bigint::BigUint;
+use primitive_types::U256;
+
+// EIP-198
+const BYZANTIUM_GAS_QUOTIENT: u64 = 20;
+// EIP-2565
+const BERLIN_MIN_GAS: u64 = 200;
+const BERLIN_GAS_QUOTIENT: u64 = 3;
+
+pub fn byzantium_run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    run(input, gas_limit, byzantium_gas_cost)
+}
+
+pub fn berlin_run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    run(input, gas_limit, berlin_gas_cost)
+}
+
+// input = base_len (32 bytes) ++ exp_len (32 bytes) ++ mod_len (32 bytes) ++ base ++ exp ++ mod, zero padded
+// output = base ** exp % mod, left padded to mod_len bytes
+fn run(
+    input: &[u8],
+    gas_limit: u64,
+    gas_cost: fn(U256, U256) -> U256,
+) -> PrecompileResult {
+    let base_len = U256::from_big_endian(&padded_input(input, 0, 32));
+    let exp_len = U256::from_big_endian(&padded_input(input, 32, 32));
+    let mod_len = U256::from_big_endian(&padded_input(input, 64, 32));
+
+    // only the first 32 bytes of the exponent are used for pricing
+    let exp_offset = to_usize_saturating(base_len.saturating_add(96.into()));
+    let exp_head_len = exp_len.min(32.into()).as_usize();
+    let exp_head = U256::from_big_endian(&padded_input(input, exp_offset, exp_head_len));
+
+    let gas_used = gas_cost(
+        base_len.max(mod_len),
+        adjusted_exponent_length(exp_len, exp_head),
+    );
+    if gas_used > gas_limit.into() {
+        return Err(EvmError::OutOfGas);
+    }
+    let gas_used = gas_used.as_u64();
+
+    if base_len.is_zero() && mod_len.is_zero() {
+        return Ok(PrecompileOutput {
+            gas_used,
+            output: Vec::new(),
+        });
+    }
+
+    let base_len = to_usize_saturating(base_len);
+    let exp_len = to_usize_saturating(exp_len);
+    let mod_len = to_usize_saturating(mod_len);
+
+    let exp_offset = 96usize.saturating_add(base_len);
+    let mod_offset = exp_offset.saturating_add(exp_len);
+
+    // the lengths may reach far past the end of the input. The modulus comes last, so when the input ends
+    // before it the result is zero and the other operands are never read. Otherwise they are entirely
+    // within the input, and only the modulus may be zero padded
+    let modulus = read_operand(input, mod_offset, mod_len);
+
+    let result = if modulus == BigUint::from(0u8) {
+        Vec::new()
+    } else {
+        let base = read_operand(input, 96, base_len);
+        let exponent = read_operand(input, exp_offset, exp_len);
+        base.modpow(&exponent, &modulus).to_bytes_be()
+    };
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: add_padding(&result, mod_len),
+    })
+}
+
+// An operand of len bytes at offset, zero padded past the end of the input. Only the bytes within the input
+// are copied, and padding a non zero value multiplies it by 256 per padding byte
+fn read_operand(input: &[u8], offset: usize, len: usize) -> BigUint {
+    let start = offset.min(input.len());
+    let end = offset.saturating_add(len).min(input.len());
+    let value = BigUint::from_bytes_be(&input[start..end]);
+
+    let padding = len - (end - start);
+    if padding == 0 || value == BigUint::from(0u8) {
+        value
+    } else {
+        value << (padding * 8)
+    }
+}
+
+fn to_usize_saturating(value: U256) -> usize {
+    if value > usize::MAX.into() {
+        usize::MAX
+    } else {
+        value.as_usize()
+    }
+}
+
+// the index of the highest set bit of the exponent, plus 8 bits per byte beyond the first 32
+fn adjusted_exponent_length(exp_len: U256, exp_head: U256) -> U256 {
+    let head_bits = U256::from(exp_head.bits().saturating_sub(1));
+
+    if exp_len <= 32.into() {
+        head_bits
+    } else {
+        (exp_len - 32)
+            .saturating_mul(8.into())
+            .saturating_add(head_bits)
+    }
+}
+
+fn byzantium_gas_cost(max_len: U256, adjusted_exp_len: U256) -> U256 {
+    let x = max_len;
+    let mult_complexity = if x <= 64.into() {
+        x * x
+    } else if x <= 1024.into() {
+        x * x / 4 + x * 96 - 3072
+    } else {
+        (x.saturating_mul(x) / 16)
+            .saturating_add(x.saturating_mul(480.into()))
+            - 199680
+    };
+
+    mult_complexity.saturating_mul(adjusted_exp_len.max(1.into())) / BYZANTIUM_GAS_QUOTIENT
+}
+
+fn berlin_gas_cost(max_len: U256, adjusted_exp_len: U256) -> U256 {
+    let words = max_len.saturating_add(7.into()) / 8;
+    let mult_complexity = words.saturating_mul(words);
+
+    let gas = mult_complexity.saturating_mul(adjusted_exp_len.max(1.into())) / BERLIN_GAS_QUOTIENT;
+    gas.max(BERLIN_MIN_GAS.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(base_len: u64, exp_len: u64, mod_len: u64, operands: &str) -> Vec<u8> {
+        let mut input = Vec::new();
+        for len in [base_len, exp_len, mod_len] {
+            input.extend_from_slice(&add_padding(&len.to_be_bytes(), 32));
+        }
+        input.extend_from_slice(&hex::decode(operands).unwrap());
+        input
+    }
+
+    #[test]
+    fn computes_the_modular_exponent() {
+        // EIP-198: 3 ** (p - 1) % p == 1 for the prime p = 2 ** 256 - 2 ** 32 - 977
+        let input = input(
+            1,
+            32,
+            32,
+            "03\
+             fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+             fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+        );
+
+        let res = berlin_run(&input, u64::MAX).unwrap();
+
+        assert_eq!(res.gas_used, 1360);
+        assert_eq!(res.output, add_padding(&[1], 32));
+    }
+
+    #[test]
+    fn pads_a_truncated_modulus_with_zeros() {
+        // 2 ** 3 % 0x0100, with the last byte of the modulus missing
+        let res = berlin_run(&input(1, 1, 2, "020301"), u64::MAX).unwrap();
+
+        assert_eq!(res.output, vec![0x00, 0x08]);
+    }
+
+    #[test]
+    fn does_not_read_operands_past_the_end_of_the_input() {
+        // an exponent far longer than the input leaves the modulus, and so the result, zero
+        let res = berlin_run(&input(1, 1 << 40, 1, "0201"), u64::MAX).unwrap();
+
+        assert_eq!(res.output, vec![0]);
+    }
+
+    #[test]
+    fn fails_without_enough_gas() {
+        let input = input(1, 1 << 40, 1, "0201");
+
+        assert!(matches!(berlin_run(&input, 1_000_000), Err(EvmError::OutOfGas)));
+    }
+}
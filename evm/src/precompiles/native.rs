@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use primitive_types::H160;
+
+pub struct NativeOutput {
+    pub output: Vec<u8>,
+    pub gas_used: u64,
+    // An unsuccessful call behaves like a revert, the output is returned as revert data
+    pub success: bool,
+}
+
+// A precompile implemented in Rust and registered by the user, e.g. to mock an oracle
+pub trait NativePrecompile {
+    fn call(&self, input: &[u8], gas_limit: u64) -> NativeOutput;
+}
+
+impl<F> NativePrecompile for F
+where
+    F: Fn(&[u8], u64) -> NativeOutput,
+{
+    fn call(&self, input: &[u8], gas_limit: u64) -> NativeOutput {
+        self(input, gas_limit)
+    }
+}
+
+// Shared between the frames of a call so the same precompiles are available at every depth
+#[derive(Clone, Default)]
+pub struct NativePrecompiles(HashMap<H160, Arc<dyn NativePrecompile>>);
+
+impl NativePrecompiles {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    // Replaces any precompile already registered at the address
+    pub fn register(&mut self, address: H160, precompile: impl NativePrecompile + 'static) {
+        self.0.insert(address, Arc::new(precompile));
+    }
+
+    pub fn remove(&mut self, address: H160) {
+        self.0.remove(&address);
+    }
+
+    pub fn get(&self, address: H160) -> Option<Arc<dyn NativePrecompile>> {
+        self.0.get(&address).cloned()
+    }
+}
+
+impl fmt::Debug for NativePrecompiles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
@@ -0,0 +1,91 @@
+use super::{check_gas, PrecompileOutput, PrecompileResult};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::EncodedPoint;
+
+// RIP-7212
+const P256VERIFY_COST: u64 = 3450;
+const INPUT_LEN: usize = 160;
+
+// input = hash (32 bytes) ++ r (32 bytes) ++ s (32 bytes) ++ x (32 bytes) ++ y (32 bytes)
+// an invalid input or signature is not an error, the output is just empty
+pub fn run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    check_gas(P256VERIFY_COST, gas_limit)?;
+
+    let output = if input.len() == INPUT_LEN && verify(input).is_some() {
+        let mut output = vec![0; 32];
+        output[31] = 1;
+        output
+    } else {
+        Vec::new()
+    };
+
+    Ok(PrecompileOutput {
+        gas_used: P256VERIFY_COST,
+        output,
+    })
+}
+
+fn verify(input: &[u8]) -> Option<()> {
+    let signature = Signature::from_slice(&input[32..96]).ok()?;
+    let point = EncodedPoint::from_affine_coordinates(
+        input[96..128].into(),
+        input[128..160].into(),
+        false,
+    );
+    let key = VerifyingKey::from_encoded_point(&point).ok()?;
+
+    key.verify_prehash(&input[..32], &signature).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::Convert;
+    use primitive_types::U256;
+
+    // Wycheproof ecdsa_secp256r1_sha256 tcId 1, as used in the RIP-7212 test suite
+    const VALID: &str = "4cee90eb86eaa050036147a12d49004b6b9c72bd725d39d4785011fe190f0b4da73bd4903f0ce3b639bbbf6e8e80d16931ff4bcf5993d58468e8fb19086e8cac36dbcd03009df8c59286b162af3bd7fcc0450c9aa81be5d10d312af6c66b1d604aebd3099c618202fcfe16ae7770b0c49ab5eadf74b754204a3bb6060e44eff37618b065f9832de4ca6ca971a7a1adc826d0f7c00181a5fb2ddf79ae00b4e10e";
+
+    #[test]
+    fn accepts_a_valid_signature() {
+        let res = run(&hex::decode(VALID).unwrap(), P256VERIFY_COST).unwrap();
+
+        assert_eq!(res.gas_used, P256VERIFY_COST);
+        assert_eq!(res.output, U256::one().to_h256().as_bytes());
+    }
+
+    #[test]
+    fn rejects_an_invalid_signature() {
+        // the same key and hash with the special case r = s = 0, which Wycheproof lists as invalid
+        let mut input = hex::decode(VALID).unwrap();
+        input[32..96].fill(0);
+
+        let res = run(&input, P256VERIFY_COST).unwrap();
+
+        assert_eq!(res.gas_used, P256VERIFY_COST);
+        assert!(res.output.is_empty());
+    }
+
+    #[test]
+    fn rejects_a_modified_hash() {
+        let mut input = hex::decode(VALID).unwrap();
+        input[0] ^= 1;
+
+        assert!(run(&input, P256VERIFY_COST).unwrap().output.is_empty());
+    }
+
+    #[test]
+    fn malformed_length_returns_nothing() {
+        let input = hex::decode(VALID).unwrap();
+        let mut longer = input.clone();
+        longer.push(0);
+
+        for input in [&input[..INPUT_LEN - 1], &longer[..], &[]] {
+            let res = run(input, P256VERIFY_COST).unwrap();
+
+            assert_eq!(res.gas_used, P256VERIFY_COST);
+            assert!(res.output.is_empty());
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use super::{check_gas, linear_cost, PrecompileOutput, PrecompileResult};
+use crate::consts::WORD_BYTES;
+use crate::helpers::add_padding;
+use ripemd::{Digest, Ripemd160};
+
+const RIPEMD160_BASE_COST: u64 = 600;
+const RIPEMD160_WORD_COST: u64 = 120;
+
+pub fn run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = linear_cost(input.len(), RIPEMD160_BASE_COST, RIPEMD160_WORD_COST);
+    check_gas(gas_used, gas_limit)?;
+
+    // the 20 byte hash is left padded to a full word
+    let hash = Ripemd160::digest(input);
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: add_padding(&hash, WORD_BYTES),
+    })
+}
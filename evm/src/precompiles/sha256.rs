@@ -0,0 +1,15 @@
+use super::{check_gas, linear_cost, PrecompileOutput, PrecompileResult};
+use sha2::{Digest, Sha256};
+
+const SHA256_BASE_COST: u64 = 60;
+const SHA256_WORD_COST: u64 = 12;
+
+pub fn run(input: &[u8], gas_limit: u64) -> PrecompileResult {
+    let gas_used = linear_cost(input.len(), SHA256_BASE_COST, SHA256_WORD_COST);
+    check_gas(gas_used, gas_limit)?;
+
+    Ok(PrecompileOutput {
+        gas_used,
+        output: Sha256::digest(input).to_vec(),
+    })
+}
@@ -0,0 +1,256 @@
+use primitive_types::{H160, U256};
+use rlp::{Encodable, RlpStream};
+use sha3::{Digest, Keccak256};
+
+use crate::machine::{EvmError, Log};
+use crate::transaction::TxType;
+
+pub const BLOOM_SIZE: usize = 256;
+
+// The 2048 bit bloom filter over the addresses and topics of logs, letting logs be searched for without
+// going through every receipt
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Bloom(pub [u8; BLOOM_SIZE]);
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self([0; BLOOM_SIZE])
+    }
+}
+
+impl std::fmt::Debug for Bloom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+impl Bloom {
+    pub fn from_logs(logs: &[Log]) -> Self {
+        let mut bloom = Self::default();
+        for log in logs {
+            bloom.accrue_log(log);
+        }
+        bloom
+    }
+
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(log.address.as_bytes());
+        for topic in &log.topics {
+            self.accrue(topic.as_bytes());
+        }
+    }
+
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (index, bit) in bloom_bits(input) {
+            self.0[index] |= bit;
+        }
+    }
+
+    // A block's bloom is the union of its receipts'
+    pub fn accrue_bloom(&mut self, other: &Bloom) {
+        for (byte, other) in self.0.iter_mut().zip(other.0) {
+            *byte |= other;
+        }
+    }
+
+    // False positives are possible, false negatives are not
+    pub fn contains(&self, input: &[u8]) -> bool {
+        bloom_bits(input)
+            .into_iter()
+            .all(|(index, bit)| self.0[index] & bit != 0)
+    }
+}
+
+// Each input sets three bits, taken from the low 11 bits of the first three pairs of bytes of its hash.
+// Bits are numbered from the end of the filter
+fn bloom_bits(input: &[u8]) -> [(usize, u8); 3] {
+    let hash = Keccak256::digest(input);
+    [0, 2, 4].map(|i| {
+        let bit = (usize::from(hash[i]) << 8 | usize::from(hash[i + 1])) % (BLOOM_SIZE * 8);
+        (BLOOM_SIZE - 1 - bit / 8, 1 << (bit % 8))
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub tx_type: TxType,
+    // The status, since Byzantium receipts no longer carry an intermediate state root
+    pub success: bool,
+    // A lower bound of the gas the specification charges, as opcodes aren't fully metered
+    pub gas_used: u64,
+    // Gas used by this and every earlier transaction in the block
+    pub cumulative_gas_used: u64,
+    pub logs_bloom: Bloom,
+    pub logs: Vec<Log>,
+    pub contract_address: Option<H160>,
+    pub effective_gas_price: U256,
+    // Not part of the consensus receipt, but usually what the caller wants to see
+    pub output: Vec<u8>,
+    pub error: Option<EvmError>,
+}
+
+impl Receipt {
+    // EIP-2718: legacy receipts are a plain RLP list, typed ones are prefixed with their transaction's type
+    pub fn encode(&self) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(4);
+        stream.append(&u8::from(self.success));
+        stream.append(&self.cumulative_gas_used);
+        stream.append(&self.logs_bloom.0.as_slice());
+        stream.append_list(&self.logs);
+
+        let mut encoded = Vec::new();
+        if self.tx_type != TxType::Legacy {
+            encoded.push(self.tx_type as u8);
+        }
+        encoded.extend_from_slice(&stream.out());
+        encoded
+    }
+}
+
+impl Encodable for Log {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream.begin_list(3);
+        stream.append(&self.address);
+        stream.append_list(&self.topics);
+        stream.append(&self.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use primitive_types::H256;
+
+    // An ERC-20 Transfer of 100 from 0x11.. to 0x22..
+    fn transfer_log() -> Log {
+        let topic = |byte| H256::from(H160::repeat_byte(byte));
+        Log {
+            address: H160::from_slice(&hex::decode("dac17f958d2ee523a2206206994597c13d831ec7").unwrap()),
+            topics: vec![
+                H256::from_slice(&Keccak256::digest(b"Transfer(address,address,uint256)")),
+                topic(0x11),
+                topic(0x22),
+            ],
+            data: H256::from_low_u64_be(100).as_bytes().to_vec(),
+        }
+    }
+
+    fn receipt(tx_type: TxType, success: bool, cumulative_gas_used: u64, logs: Vec<Log>) -> Receipt {
+        Receipt {
+            tx_type,
+            success,
+            gas_used: cumulative_gas_used,
+            cumulative_gas_used,
+            logs_bloom: Bloom::from_logs(&logs),
+            logs,
+            contract_address: None,
+            effective_gas_price: U256::zero(),
+            output: Vec::new(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn bloom_matches_a_known_vector() {
+        // go-ethereum's TestBloomExtensively
+        let mut bloom = Bloom::default();
+        for i in 0..100 {
+            bloom.accrue(format!("xxxxxxxxxx data {} yyyyyyyyyyyyyy", i).as_bytes());
+        }
+
+        assert_eq!(
+            hex::encode(Keccak256::digest(bloom.0)),
+            "c8d3ca65cdb4874300a9e39475508f23ed6da09fdbc487f89a2dcf50b09eb263"
+        );
+    }
+
+    #[test]
+    fn logs_set_the_bits_of_their_address_and_topics() {
+        let log = transfer_log();
+        let mut expected = Bloom::default();
+        let bits = [
+            (46, 0x01),
+            (49, 0x08),
+            (60, 0x20),
+            (75, 0x08),
+            (123, 0x10),
+            (125, 0x02),
+            (157, 0x10),
+            (160, 0x24),
+            (171, 0x80),
+            (195, 0x02),
+            (242, 0x08),
+        ];
+        for (index, bit) in bits {
+            expected.0[index] = bit;
+        }
+
+        let bloom = Bloom::from_logs(std::slice::from_ref(&log));
+
+        assert_eq!(bloom, expected);
+        assert!(bloom.contains(log.address.as_bytes()));
+        assert!(log.topics.iter().all(|topic| bloom.contains(topic.as_bytes())));
+        // the data isn't part of the bloom
+        assert!(!bloom.contains(&log.data));
+        assert!(!bloom.contains(H160::repeat_byte(0x33).as_bytes()));
+    }
+
+    #[test]
+    fn accruing_a_bloom_takes_the_union() {
+        let mut bloom = Bloom::default();
+        bloom.accrue(b"first");
+        let mut other = Bloom::default();
+        other.accrue(b"second");
+
+        bloom.accrue_bloom(&other);
+
+        let mut both = Bloom::default();
+        both.accrue(b"first");
+        both.accrue(b"second");
+        assert_eq!(bloom, both);
+        assert!(bloom.contains(b"first") && bloom.contains(b"second"));
+    }
+
+    #[test]
+    fn legacy_receipts_are_a_plain_list() {
+        let encoded = receipt(TxType::Legacy, false, 21000, Vec::new()).encode();
+
+        // a 264 byte list of the failed status, the gas used, an empty bloom and no logs
+        let mut expected = hex::decode("f9010880825208b90100").unwrap();
+        expected.extend([0; BLOOM_SIZE]);
+        expected.push(0xc0);
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn typed_receipts_are_prefixed_with_their_type() {
+        let legacy = receipt(TxType::Legacy, true, 21000, Vec::new()).encode();
+        assert_eq!(legacy[0], 0xf9);
+
+        for (tx_type, prefix) in [
+            (TxType::AccessList, 0x01),
+            (TxType::DynamicFee, 0x02),
+            (TxType::Blob, 0x03),
+            (TxType::SetCode, 0x04),
+        ] {
+            let encoded = receipt(tx_type, true, 21000, Vec::new()).encode();
+            assert_eq!(encoded[0], prefix, "{:?}", tx_type);
+            assert_eq!(encoded[1..], legacy, "{:?}", tx_type);
+        }
+    }
+
+    #[test]
+    fn receipts_with_logs_match_a_known_vector() {
+        let legacy = receipt(TxType::Legacy, true, 50000, vec![transfer_log()]);
+        let dynamic_fee = receipt(TxType::DynamicFee, true, 50000, vec![transfer_log()]);
+
+        assert_eq!(
+            hex::encode(Keccak256::digest(legacy.encode())),
+            "a5080c5fd0bd7a98bdca5a5e2a6567e6abd9c1ed035c8eb67e7497892eec0f9c"
+        );
+        assert_eq!(
+            hex::encode(Keccak256::digest(dynamic_fee.encode())),
+            "d0f5776157d478daabf1bc1fa322bd13bafb07d7e749c7a8c2d1fbbfc40e2bf9"
+        );
+    }
+}
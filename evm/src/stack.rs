@@ -36,6 +36,6 @@ impl Stack {
     }
 
     pub fn data(&self) -> Vec<U256> {
-        self.data.to_vec().into_iter().rev().collect()
+        self.data.iter().rev().copied().collect()
     }
 }
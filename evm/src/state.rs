@@ -1,27 +1,70 @@
 use std::collections::HashMap;
-use primitive_types::{U256, H160};
+use primitive_types::{U256, H160, H256};
+use sha3::{Digest, Keccak256};
 
-#[derive(Debug, Clone)]
+use crate::database::Database;
+use crate::trie::{account_key, encode_account, storage_root, trie_root, EMPTY_CODE_HASH};
+
+#[derive(Debug, Clone, Default)]
 pub struct Account {
     pub balance: U256,
     pub code: Vec<u8>,
+    pub nonce: u64,
+    pub storage: HashMap<U256, U256>,
 }
 
 impl Account {
     pub fn new(balance: U256, code: Vec<u8>) -> Self {
-        Self { balance, code }
+        Self {
+            balance,
+            code,
+            ..Default::default()
+        }
+    }
+
+    // EIP-161: empty accounts are treated as if they don't exist, and are left out of the state trie
+    pub fn is_empty(&self) -> bool {
+        self.nonce == 0 && self.balance.is_zero() && self.code.is_empty()
+    }
+
+    pub fn storage_root(&self) -> H256 {
+        storage_root(self.storage.iter().map(|(key, value)| (*key, *value)))
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BalanceOverflow(H160),
+    BalanceUnderflow(H160),
+}
+
+// Enough to undo a single change made through the Database trait
+#[derive(Clone)]
+enum JournalEntry {
+    AccountCreated(H160),
+    AccountDestroyed(H160, Account),
+    Balance(H160, U256),
+    Nonce(H160, u64),
+    Code(H160, Vec<u8>),
+    Storage(H160, U256, Option<U256>),
+}
+
+// In memory implementation of the Database
 // TODO: Update to use BTreeMap
 // TODO: Move out of context
-#[derive(Clone)]
-pub struct State(pub HashMap<H160, Account>);
+#[derive(Clone, Default)]
+pub struct State {
+    pub accounts: HashMap<H160, Account>,
+    pub block_hashes: HashMap<U256, H256>,
+    // Changes made since the outermost checkpoint. Changes made without a checkpoint can't be reverted,
+    // so aren't recorded
+    journal: Vec<JournalEntry>,
+    open_checkpoints: usize,
+}
 
 impl State {
     pub fn new() -> Self {
-        let map = HashMap::<H160, Account>::new();
-        Self(map)
+        Self::default()
     }
 
     pub fn add_accounts(&mut self, address_balances: &Vec<(H160, Vec<u8>, Vec<u8>)>) {
@@ -35,26 +78,11 @@ impl State {
     }
 
     pub fn add_account(&mut self, address: H160, balance: U256, code: Vec<u8>) {
-        self.0.insert(address, Account::new(balance, code));
-    }
-
-    pub fn destruct_account(&mut self, address: H160) -> U256 {
-        let account = self.0.remove(&address);
-        match account {
-            Some(account) => account.balance,
-            None => 0.into()
-        }
-    }
-
-    pub fn add_or_update_account(&mut self, address: H160, balance: U256, code: Vec<u8>) {
-        let prev_balance = self.get_account_balance(address);
-        let new_balance = balance + prev_balance;
-
-        self.0.insert(address, Account::new(new_balance, code));
+        self.accounts.insert(address, Account::new(balance, code));
     }
 
     pub fn get_account_code(&self, address: H160) -> Vec<u8> {
-        let balance = self.0.get(&address);
+        let balance = self.accounts.get(&address);
 
         match balance {
             Some(account) => account.code.clone(),
@@ -63,7 +91,7 @@ impl State {
     }
 
     pub fn get_account_balance(&self, address: H160) -> U256 {
-        let account = self.0.get(&address);
+        let account = self.accounts.get(&address);
 
         match account {
             Some(account) => account.balance,
@@ -72,21 +100,306 @@ impl State {
     }
 
     pub fn get_account(&self, address: H160) -> Option<&Account> {
-        let account = self.0.get(&address);
+        self.accounts.get(&address)
+    }
+
+    pub fn set_block_hash(&mut self, number: U256, hash: H256) {
+        self.block_hashes.insert(number, hash);
+    }
+
+    fn record(&mut self, entry: JournalEntry) {
+        if self.open_checkpoints > 0 {
+            self.journal.push(entry);
+        }
+    }
+
+    // The account to change, journaling its creation if it doesn't exist yet
+    fn account_mut(&mut self, address: H160) -> &mut Account {
+        if !self.accounts.contains_key(&address) {
+            self.record(JournalEntry::AccountCreated(address));
+        }
+        self.accounts.entry(address).or_default()
+    }
+
+    fn undo(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::AccountCreated(address) => {
+                self.accounts.remove(&address);
+            }
+            JournalEntry::AccountDestroyed(address, account) => {
+                self.accounts.insert(address, account);
+            }
+            JournalEntry::Balance(address, balance) => {
+                self.accounts.entry(address).or_default().balance = balance;
+            }
+            JournalEntry::Nonce(address, nonce) => {
+                self.accounts.entry(address).or_default().nonce = nonce;
+            }
+            JournalEntry::Code(address, code) => {
+                self.accounts.entry(address).or_default().code = code;
+            }
+            JournalEntry::Storage(address, key, value) => {
+                let storage = &mut self.accounts.entry(address).or_default().storage;
+                match value {
+                    Some(value) => storage.insert(key, value),
+                    None => storage.remove(&key),
+                };
+            }
+        }
+    }
+}
+
+impl Database for State {
+    type Error = StateError;
+
+    fn balance(&self, address: H160) -> Result<U256, StateError> {
+        Ok(self.get_account_balance(address))
+    }
+
+    fn code(&self, address: H160) -> Result<Vec<u8>, StateError> {
+        Ok(self.get_account_code(address))
+    }
+
+    fn storage(&self, address: H160, key: U256) -> Result<U256, StateError> {
+        Ok(self
+            .accounts
+            .get(&address)
+            .and_then(|account| account.storage.get(&key).copied())
+            .unwrap_or_default())
+    }
+
+    fn nonce(&self, address: H160) -> Result<u64, StateError> {
+        Ok(self
+            .accounts
+            .get(&address)
+            .map(|account| account.nonce)
+            .unwrap_or_default())
+    }
+
+    fn block_hash(&self, number: U256) -> Result<H256, StateError> {
+        Ok(self.block_hashes.get(&number).copied().unwrap_or_default())
+    }
+
+    fn set_storage(&mut self, address: H160, key: U256, value: U256) -> Result<(), StateError> {
+        let previous = self.account_mut(address).storage.insert(key, value);
+        self.record(JournalEntry::Storage(address, key, previous));
+        Ok(())
+    }
+
+    fn set_code(&mut self, address: H160, code: Vec<u8>) -> Result<(), StateError> {
+        let previous = std::mem::replace(&mut self.account_mut(address).code, code);
+        self.record(JournalEntry::Code(address, previous));
+        Ok(())
+    }
+
+    fn set_nonce(&mut self, address: H160, nonce: u64) -> Result<(), StateError> {
+        let previous = std::mem::replace(&mut self.account_mut(address).nonce, nonce);
+        self.record(JournalEntry::Nonce(address, previous));
+        Ok(())
+    }
+
+    fn increment_balance(&mut self, address: H160, amount: U256) -> Result<(), StateError> {
+        let account = self.account_mut(address);
+        let previous = account.balance;
+        account.balance = previous
+            .checked_add(amount)
+            .ok_or(StateError::BalanceOverflow(address))?;
+        self.record(JournalEntry::Balance(address, previous));
+        Ok(())
+    }
 
-        account
+    fn decrement_balance(&mut self, address: H160, amount: U256) -> Result<(), StateError> {
+        let account = self.account_mut(address);
+        let previous = account.balance;
+        account.balance = previous
+            .checked_sub(amount)
+            .ok_or(StateError::BalanceUnderflow(address))?;
+        self.record(JournalEntry::Balance(address, previous));
+        Ok(())
     }
 
-    pub fn increment_balance(&mut self, address: H160, extra: U256) {
-        let account = self.get_account(address);
+    fn destruct_account(&mut self, address: H160) -> Result<U256, StateError> {
+        let account = self.accounts.remove(&address);
         match account {
             Some(account) => {
-                let new_balance = account.balance + extra;
-                self.add_account(address, new_balance, account.code.clone())
-            }
-            None => {
-                self.add_account(address, extra, Vec::new())
+                let balance = account.balance;
+                self.record(JournalEntry::AccountDestroyed(address, account));
+                Ok(balance)
             }
+            None => Ok(0.into())
+        }
+    }
+
+    // Empty accounts are left out as from EIP-161, which every supported fork includes, so the roots of
+    // earlier blocks can't be computed
+    fn state_root(&self) -> Result<H256, StateError> {
+        let accounts = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| !account.is_empty())
+            .map(|(address, account)| {
+                let code_hash = if account.code.is_empty() {
+                    EMPTY_CODE_HASH
+                } else {
+                    H256::from_slice(&Keccak256::digest(&account.code))
+                };
+                let leaf = encode_account(account.nonce, account.balance, account.storage_root(), code_hash);
+                (account_key(*address), leaf)
+            });
+        Ok(trie_root(accounts))
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.open_checkpoints += 1;
+        self.journal.len()
+    }
+
+    fn revert(&mut self, checkpoint: usize) {
+        self.open_checkpoints = self.open_checkpoints.saturating_sub(1);
+        while self.journal.len() > checkpoint {
+            let entry = self.journal.pop().unwrap();
+            self.undo(entry);
+        }
+    }
+
+    fn commit(&mut self, _checkpoint: usize) {
+        // nothing can be reverted once the outermost checkpoint is kept
+        self.open_checkpoints = self.open_checkpoints.saturating_sub(1);
+        if self.open_checkpoints == 0 {
+            self.journal.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(byte: u8) -> H160 {
+        H160::repeat_byte(byte)
+    }
+
+    #[test]
+    fn revert_undoes_every_change_since_the_checkpoint() {
+        let mut state = State::new();
+        state.add_account(address(1), 10.into(), vec![0x00]);
+        state.set_storage(address(1), 1.into(), 2.into()).unwrap();
+
+        let checkpoint = state.checkpoint();
+        state.decrement_balance(address(1), 4.into()).unwrap();
+        state.increment_balance(address(2), 4.into()).unwrap();
+        state.set_storage(address(1), 1.into(), 3.into()).unwrap();
+        state.set_storage(address(1), 2.into(), 3.into()).unwrap();
+        state.set_code(address(1), vec![0xfe]).unwrap();
+        state.set_nonce(address(1), 7).unwrap();
+        state.revert(checkpoint);
+
+        assert_eq!(state.balance(address(1)).unwrap(), 10.into());
+        assert_eq!(state.storage(address(1), 1.into()).unwrap(), 2.into());
+        assert!(!state.accounts[&address(1)].storage.contains_key(&2.into()));
+        assert_eq!(state.code(address(1)).unwrap(), vec![0x00]);
+        assert_eq!(state.nonce(address(1)).unwrap(), 0);
+        assert!(state.get_account(address(2)).is_none());
+    }
+
+    #[test]
+    fn revert_restores_destructed_accounts() {
+        let mut state = State::new();
+        state.add_account(address(1), 10.into(), vec![0x00]);
+
+        let checkpoint = state.checkpoint();
+        assert_eq!(state.destruct_account(address(1)).unwrap(), 10.into());
+        state.revert(checkpoint);
+
+        assert_eq!(state.balance(address(1)).unwrap(), 10.into());
+        assert_eq!(state.code(address(1)).unwrap(), vec![0x00]);
+    }
+
+    #[test]
+    fn reverting_an_outer_checkpoint_undoes_committed_inner_ones() {
+        let mut state = State::new();
+
+        let outer = state.checkpoint();
+        state.set_nonce(address(1), 1).unwrap();
+        let inner = state.checkpoint();
+        state.set_nonce(address(1), 2).unwrap();
+        state.commit(inner);
+        assert_eq!(state.nonce(address(1)).unwrap(), 2);
+
+        state.revert(outer);
+        assert!(state.get_account(address(1)).is_none());
+    }
+
+    #[test]
+    fn checkpoints_at_the_same_position_nest() {
+        let mut state = State::new();
+
+        let outer = state.checkpoint();
+        let inner = state.checkpoint();
+        state.set_nonce(address(1), 1).unwrap();
+        state.commit(inner);
+
+        state.revert(outer);
+        assert!(state.get_account(address(1)).is_none());
+    }
+
+    #[test]
+    fn balances_fail_rather_than_wrap() {
+        let mut state = State::new();
+        state.add_account(address(1), U256::MAX, Vec::new());
+
+        assert_eq!(
+            state.increment_balance(address(1), 1.into()),
+            Err(StateError::BalanceOverflow(address(1)))
+        );
+        assert_eq!(
+            state.decrement_balance(address(2), 1.into()),
+            Err(StateError::BalanceUnderflow(address(2)))
+        );
+        assert_eq!(state.balance(address(1)).unwrap(), U256::MAX);
+    }
+
+    #[test]
+    fn state_root_of_a_genesis_allocation() {
+        let mut state = State::new();
+        for byte in 1..=8 {
+            state.add_account(H160::from_low_u64_be(byte), 1.into(), Vec::new());
+        }
+        let from_hex = |hex: &str| H160::from_slice(&hex::decode(hex).unwrap());
+        let funded = from_hex("a94f5374fce5edbc8e2a8697c15331677e6ebf0b");
+        state.add_account(funded, U256::exp10(18), Vec::new());
+        let contract = from_hex("1000000000000000000000000000000000000000");
+        state.add_account(contract, 0.into(), hex::decode("600160005500").unwrap());
+        state.set_nonce(contract, 1).unwrap();
+        for (key, value) in [(0, 1), (1, 0x2a), (2, 0)] {
+            state.set_storage(contract, key.into(), value.into()).unwrap();
         }
+        // empty accounts, such as one only touched, are left out
+        state.add_account(address(0x20), 0.into(), Vec::new());
+
+        // computed with a separate reference implementation of Keccak, RLP and the trie
+        let expected = "43a65d3dda80d0c3f9713bf23dc0c44b1593aaafca9fb639ae30ebc2ed55ee4f";
+        assert_eq!(state.state_root().unwrap(), H256::from_slice(&hex::decode(expected).unwrap()));
+    }
+
+    #[test]
+    fn state_without_accounts_has_the_empty_root() {
+        assert_eq!(State::new().state_root().unwrap(), crate::trie::EMPTY_ROOT);
+    }
+
+    #[test]
+    fn changes_outside_a_checkpoint_are_not_journaled() {
+        let mut state = State::new();
+        state.increment_balance(address(1), 10.into()).unwrap();
+        state.set_storage(address(1), 1.into(), 2.into()).unwrap();
+        assert!(state.journal.is_empty());
+
+        let checkpoint = state.checkpoint();
+        state.set_nonce(address(1), 1).unwrap();
+        assert_eq!(state.journal.len(), 1);
+        state.revert(checkpoint);
+
+        assert_eq!(state.nonce(address(1)).unwrap(), 0);
+        assert_eq!(state.balance(address(1)).unwrap(), 10.into());
     }
 }
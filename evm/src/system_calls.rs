@@ -0,0 +1,203 @@
+use primitive_types::{H160, H256, U256};
+
+use crate::block::Block;
+use crate::context::Context;
+use crate::database::Database;
+use crate::evm;
+use crate::executor::BlockExecutionError;
+use crate::fork::Fork;
+use crate::helpers::Convert;
+use crate::machine::EvmError;
+
+// The caller of system calls, which isn't charged for them
+pub const SYSTEM_ADDRESS: H160 = H160([
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xfe,
+]);
+
+// EIP-4788
+pub const BEACON_ROOTS_ADDRESS: H160 = H160([
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22, 0xd0,
+    0xbe, 0xac, 0x02,
+]);
+
+// EIP-2935
+pub const HISTORY_STORAGE_ADDRESS: H160 = H160([
+    0x00, 0x00, 0xf9, 0x08, 0x27, 0xf1, 0xc5, 0x3a, 0x10, 0xcb, 0x7a, 0x02, 0x33, 0x5b, 0x17, 0x53, 0x20,
+    0x00, 0x29, 0x35,
+]);
+pub const HISTORY_SERVE_WINDOW: u64 = 8191;
+
+const SYSTEM_CALL_GAS_LIMIT: u64 = 30_000_000;
+
+// A system call whose contract failed. Its changes are reverted but the block stays valid
+#[derive(Debug, Clone)]
+pub struct SystemCallFailure {
+    pub address: H160,
+    pub error: Option<EvmError>,
+}
+
+// Made at the start of each block, before any transaction. A contract which hasn't been deployed yet is
+// skipped, and one which fails is returned rather than failing the block. Only the database failing does
+pub fn apply_system_calls<D: Database>(
+    block: &Block,
+    state: &mut D,
+) -> Result<Vec<SystemCallFailure>, BlockExecutionError> {
+    let mut failures = Vec::new();
+    if block.fork.is_enabled(Fork::Cancun) {
+        let root = block.parent_beacon_block_root;
+        failures.extend(system_call(BEACON_ROOTS_ADDRESS, root, block, state)?);
+    }
+    if block.fork.is_enabled(Fork::Prague) {
+        failures.extend(system_call(HISTORY_STORAGE_ADDRESS, block.parent_hash, block, state)?);
+    }
+    Ok(failures)
+}
+
+fn system_call<D: Database>(
+    address: H160,
+    data: H256,
+    block: &Block,
+    state: &mut D,
+) -> Result<Option<SystemCallFailure>, BlockExecutionError> {
+    let code = state.code(address).map_err(BlockExecutionError::database)?;
+    if code.is_empty() {
+        return Ok(None);
+    }
+
+    let checkpoint = state.checkpoint();
+    let call_data = hex::encode(data);
+    let mut context = Context::new(
+        address,
+        SYSTEM_ADDRESS,
+        SYSTEM_ADDRESS,
+        U256::zero(),
+        U256::zero(),
+        &call_data,
+        state,
+        false,
+    );
+    context.gas_limit = SYSTEM_CALL_GAS_LIMIT;
+
+    let res = evm(code, context, block.clone());
+    if res.success {
+        state.commit(checkpoint);
+        return Ok(None);
+    }
+    state.revert(checkpoint);
+
+    match res.error {
+        Some(EvmError::Database(err)) => Err(BlockExecutionError::Database(err)),
+        error => Ok(Some(SystemCallFailure { address, error })),
+    }
+}
+
+// The hash of the given block as stored by the EIP-2935 history contract, if it has been
+pub(crate) fn history_block_hash<D: Database>(
+    state: &D,
+    number: U256,
+) -> Result<Option<H256>, D::Error> {
+    let slot = number % U256::from(HISTORY_SERVE_WINDOW);
+    let hash = state.storage(HISTORY_STORAGE_ADDRESS, slot)?;
+    if hash.is_zero() {
+        Ok(None)
+    } else {
+        Ok(Some(hash.to_h256()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+
+    // The contracts as deployed by EIP-4788 and EIP-2935
+    const BEACON_ROOTS_CODE: &str = "\
+        3373fffffffffffffffffffffffffffffffffffffffe14604d57602036146024575f5ffd5b5f35801560495762001fff\
+        810690815414603c575f5ffd5b62001fff01545f5260205ff35b5f5ffd5b62001fff42064281555f359062001fff0155\
+        00";
+    const HISTORY_STORAGE_CODE: &str = "\
+        3373fffffffffffffffffffffffffffffffffffffffe14604657602036036042575f35600143038111604257611fff81\
+        430311604257611fff9006545f5260205ff35b5f5ffd5b5f35611fff60014303065500";
+
+    fn block(fork: Fork) -> Block {
+        Block {
+            number: 10_000.into(),
+            timestamp: (2 * HISTORY_SERVE_WINDOW + 5).into(),
+            parent_hash: H256::repeat_byte(0xaa),
+            parent_beacon_block_root: H256::repeat_byte(0xbb),
+            fork,
+            ..Default::default()
+        }
+    }
+
+    fn state() -> State {
+        let mut state = State::new();
+        state.add_account(BEACON_ROOTS_ADDRESS, 0.into(), hex::decode(BEACON_ROOTS_CODE).unwrap());
+        state.add_account(HISTORY_STORAGE_ADDRESS, 0.into(), hex::decode(HISTORY_STORAGE_CODE).unwrap());
+        state
+    }
+
+    #[test]
+    fn beacon_root_is_stored_in_the_timestamp_ring_buffer() {
+        let mut state = state();
+
+        let failures = apply_system_calls(&block(Fork::Cancun), &mut state).unwrap();
+
+        assert!(failures.is_empty());
+        // timestamp % 8191 holds the timestamp, 8191 slots later holds the root
+        let timestamp = block(Fork::Cancun).timestamp;
+        assert_eq!(state.storage(BEACON_ROOTS_ADDRESS, 5.into()).unwrap(), timestamp);
+        assert_eq!(
+            state.storage(BEACON_ROOTS_ADDRESS, (5 + HISTORY_SERVE_WINDOW).into()).unwrap(),
+            H256::repeat_byte(0xbb).to_u256()
+        );
+    }
+
+    #[test]
+    fn parent_hash_is_served_by_the_history_contract_from_prague() {
+        let mut state = state();
+        let parent = U256::from(9_999);
+
+        apply_system_calls(&block(Fork::Cancun), &mut state).unwrap();
+        assert_eq!(history_block_hash(&state, parent).unwrap(), None);
+
+        let failures = apply_system_calls(&block(Fork::Prague), &mut state).unwrap();
+        assert!(failures.is_empty());
+        assert_eq!(history_block_hash(&state, parent).unwrap(), Some(H256::repeat_byte(0xaa)));
+        // The slot is shared with the block HISTORY_SERVE_WINDOW earlier, which isn't served any more
+        let slot = parent % U256::from(HISTORY_SERVE_WINDOW);
+        let stored = state.storage(HISTORY_STORAGE_ADDRESS, slot).unwrap();
+        assert_eq!(stored, H256::repeat_byte(0xaa).to_u256());
+    }
+
+    #[test]
+    fn system_calls_are_skipped_before_their_fork_or_deployment() {
+        let mut state = state();
+        apply_system_calls(&block(Fork::Shanghai), &mut state).unwrap();
+        assert_eq!(state.storage(BEACON_ROOTS_ADDRESS, 5.into()).unwrap(), U256::zero());
+
+        let mut state = State::new();
+        let failures = apply_system_calls(&block(Fork::Prague), &mut state).unwrap();
+        assert!(failures.is_empty());
+        assert!(state.get_account(BEACON_ROOTS_ADDRESS).is_none());
+    }
+
+    #[test]
+    fn failed_system_call_is_reported_and_reverted() {
+        let mut state = state();
+        // SSTORE(0, 1) then INVALID
+        let code = hex::decode("6001600055fe").unwrap();
+        state.add_account(HISTORY_STORAGE_ADDRESS, 0.into(), code);
+
+        let failures = apply_system_calls(&block(Fork::Prague), &mut state).unwrap();
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].address, HISTORY_STORAGE_ADDRESS);
+        assert!(failures[0].error.is_some());
+        assert_eq!(state.storage(HISTORY_STORAGE_ADDRESS, 0.into()).unwrap(), U256::zero());
+        // The beacon root call before it still succeeded
+        let timestamp = state.storage(BEACON_ROOTS_ADDRESS, 5.into()).unwrap();
+        assert_eq!(timestamp, block(Fork::Prague).timestamp);
+    }
+}
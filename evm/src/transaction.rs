@@ -0,0 +1,1015 @@
+use std::fmt::Debug;
+
+use primitive_types::{H160, H256, U256};
+
+use crate::access_list::AccessSet;
+use crate::authorization::{delegated_address, delegation_designator, Authorization};
+use crate::block::Block;
+use crate::consts::{
+    GAS_PER_BLOB, MAX_INITCODE_SIZE, MAX_REFUND_QUOTIENT, PER_AUTH_BASE_COST, PER_EMPTY_ACCOUNT_COST,
+    VERSIONED_HASH_VERSION_KZG,
+};
+use crate::context::Context;
+use crate::database::Database;
+use crate::evm;
+use crate::fees::{effective_gas_price, split_fee};
+use crate::fork::Fork;
+use crate::helpers::{check_deployed_code, create_address, has_contract};
+use crate::intrinsic_gas::{intrinsic_gas, IntrinsicGas};
+use crate::machine::{EvmError, EvmResult};
+use crate::precompiles::NativePrecompiles;
+use crate::receipt::{Bloom, Receipt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: H160,
+    pub storage_keys: Vec<H256>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TxKind {
+    Call(H160),
+    Create,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TxFees {
+    Legacy {
+        gas_price: U256,
+    },
+    // EIP-1559
+    Dynamic {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+// EIP-2718 transaction types, which also prefix the encoding of their receipts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxType {
+    Legacy = 0,
+    // EIP-2930
+    AccessList = 1,
+    // EIP-1559
+    DynamicFee = 2,
+    // EIP-4844
+    Blob = 3,
+    // EIP-7702
+    SetCode = 4,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    // Which of the fields below may be set depends on the type, see validate_type
+    pub tx_type: TxType,
+    pub from: H160,
+    pub to: TxKind,
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub gas_limit: u64,
+    pub fees: TxFees,
+    pub nonce: u64,
+    pub access_list: Vec<AccessListItem>,
+    // EIP-4844
+    pub max_fee_per_blob_gas: U256,
+    pub blob_versioned_hashes: Vec<H256>,
+    // EIP-7702
+    pub authorization_list: Vec<Authorization>,
+}
+
+// Reasons a transaction can not be included in a block at all, as opposed to failing during execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    NonceMismatch { expected: u64, actual: u64 },
+    // EIP-2681: the sender's nonce can't be incremented past 2^64 - 1
+    NonceMax,
+    InsufficientFunds { required: U256, available: U256 },
+    IntrinsicGasTooLow { required: u64, limit: u64 },
+    FeeCapBelowBaseFee,
+    PriorityFeeAboveFeeCap,
+    InitcodeSizeExceeded,
+    // The type isn't valid at the block's fork
+    TypeNotEnabled(TxType),
+    // A field is set which the type doesn't have, or the fees are of the other kind
+    FieldsDontMatchType(TxType),
+    // EIP-4844: blob transactions carry at least one blob, and can't create a contract
+    BlobCreate,
+    NoBlobs,
+    TooManyBlobs { count: usize, max: usize },
+    InvalidBlobVersionedHash(H256),
+    BlobFeeCapBelowBlobBaseFee,
+    // EIP-7702: set code transactions carry at least one authorization, and can't create a contract
+    SetCodeCreate,
+    EmptyAuthorizationList,
+    // Paying the value, a refund or the fee would take the recipient's balance past 2^256 - 1
+    BalanceOverflow { address: H160 },
+    // The database failed, so whether the transaction is valid isn't known
+    Database(String),
+}
+
+impl TransactionError {
+    fn database(err: impl Debug) -> Self {
+        Self::Database(format!("{:?}", err))
+    }
+}
+
+impl Transaction {
+    // The price actually paid per unit of gas
+    pub fn effective_gas_price(&self, basefee: U256) -> U256 {
+        match self.fees {
+            TxFees::Legacy { gas_price } => gas_price,
+            TxFees::Dynamic {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => effective_gas_price(max_fee_per_gas, max_priority_fee_per_gas, basefee),
+        }
+    }
+
+    // The most the sender can be charged per unit of gas, which their balance must cover
+    fn max_gas_price(&self) -> U256 {
+        match self.fees {
+            TxFees::Legacy { gas_price } => gas_price,
+            TxFees::Dynamic { max_fee_per_gas, .. } => max_fee_per_gas,
+        }
+    }
+
+    // EIP-4844: blob gas is priced separately from the gas limit and isn't refunded
+    pub fn blob_gas(&self) -> u64 {
+        GAS_PER_BLOB * self.blob_versioned_hashes.len() as u64
+    }
+
+    pub fn intrinsic_gas(&self, fork: Fork) -> IntrinsicGas {
+        let is_create = matches!(self.to, TxKind::Create);
+        intrinsic_gas(&self.data, is_create, &self.access_list, &self.authorization_list, fork)
+    }
+
+    // Checks the fields which are set are those of the transaction's type
+    fn validate_type(&self, block: &Block) -> Result<(), TransactionError> {
+        let (enabled_at, dynamic_fees) = match self.tx_type {
+            TxType::Legacy => (Fork::Byzantium, false),
+            TxType::AccessList => (Fork::Berlin, false),
+            TxType::DynamicFee => (Fork::London, true),
+            TxType::Blob => (Fork::Cancun, true),
+            TxType::SetCode => (Fork::Prague, true),
+        };
+        if !block.fork.is_enabled(enabled_at) {
+            return Err(TransactionError::TypeNotEnabled(self.tx_type));
+        }
+
+        let has_blob_fields = !self.blob_versioned_hashes.is_empty() || !self.max_fee_per_blob_gas.is_zero();
+        let mismatch = matches!(self.fees, TxFees::Dynamic { .. }) != dynamic_fees
+            || (self.tx_type == TxType::Legacy && !self.access_list.is_empty())
+            || (self.tx_type != TxType::Blob && has_blob_fields)
+            || (self.tx_type != TxType::SetCode && !self.authorization_list.is_empty());
+        if mismatch {
+            return Err(TransactionError::FieldsDontMatchType(self.tx_type));
+        }
+
+        match self.tx_type {
+            TxType::Blob => {
+                if matches!(self.to, TxKind::Create) {
+                    return Err(TransactionError::BlobCreate);
+                }
+                if self.blob_versioned_hashes.is_empty() {
+                    return Err(TransactionError::NoBlobs);
+                }
+                let max = max_blobs_per_block(block.fork);
+                if self.blob_versioned_hashes.len() > max {
+                    return Err(TransactionError::TooManyBlobs {
+                        count: self.blob_versioned_hashes.len(),
+                        max,
+                    });
+                }
+                if let Some(hash) = self
+                    .blob_versioned_hashes
+                    .iter()
+                    .find(|hash| hash[0] != VERSIONED_HASH_VERSION_KZG)
+                {
+                    return Err(TransactionError::InvalidBlobVersionedHash(*hash));
+                }
+                if self.max_fee_per_blob_gas < block.blob_basefee() {
+                    return Err(TransactionError::BlobFeeCapBelowBlobBaseFee);
+                }
+            }
+            TxType::SetCode => {
+                if matches!(self.to, TxKind::Create) {
+                    return Err(TransactionError::SetCodeCreate);
+                }
+                if self.authorization_list.is_empty() {
+                    return Err(TransactionError::EmptyAuthorizationList);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn validate<D: Database>(
+        &self,
+        block: &Block,
+        state: &D,
+    ) -> Result<IntrinsicGas, TransactionError> {
+        self.validate_type(block)?;
+
+        let london = block.fork.is_enabled(Fork::London);
+        match self.fees {
+            TxFees::Legacy { gas_price } => {
+                if london && gas_price < block.basefee {
+                    return Err(TransactionError::FeeCapBelowBaseFee);
+                }
+            }
+            TxFees::Dynamic {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                if max_priority_fee_per_gas > max_fee_per_gas {
+                    return Err(TransactionError::PriorityFeeAboveFeeCap);
+                }
+                if max_fee_per_gas < block.basefee {
+                    return Err(TransactionError::FeeCapBelowBaseFee);
+                }
+            }
+        }
+
+        let expected = state.nonce(self.from).map_err(TransactionError::database)?;
+        if self.nonce != expected {
+            return Err(TransactionError::NonceMismatch {
+                expected,
+                actual: self.nonce,
+            });
+        }
+        if self.nonce == u64::MAX {
+            return Err(TransactionError::NonceMax);
+        }
+
+        // EIP-3860
+        if matches!(self.to, TxKind::Create)
+            && block.fork.is_enabled(Fork::Shanghai)
+            && self.data.len() > MAX_INITCODE_SIZE
+        {
+            return Err(TransactionError::InitcodeSizeExceeded);
+        }
+
+        let intrinsic_gas = self.intrinsic_gas(block.fork);
+        if intrinsic_gas.minimum_gas_limit() > self.gas_limit {
+            return Err(TransactionError::IntrinsicGasTooLow {
+                required: intrinsic_gas.minimum_gas_limit(),
+                limit: self.gas_limit,
+            });
+        }
+
+        // gas_limit * gas_price + blob_gas * blob_gas_price + value, overflowing means it can never be
+        // covered
+        let available = state.balance(self.from).map_err(TransactionError::database)?;
+        let blob_cost = U256::from(self.blob_gas()).checked_mul(self.max_fee_per_blob_gas);
+        let required = U256::from(self.gas_limit)
+            .checked_mul(self.max_gas_price())
+            .zip(blob_cost)
+            .and_then(|(cost, blob_cost)| cost.checked_add(blob_cost))
+            .and_then(|cost| cost.checked_add(self.value))
+            .unwrap_or(U256::MAX);
+        if required > available {
+            return Err(TransactionError::InsufficientFunds {
+                required,
+                available,
+            });
+        }
+
+        Ok(intrinsic_gas)
+    }
+}
+
+// Executes a transaction on top of the given state, returning its receipt. An invalid transaction leaves
+// the state as it was
+//
+// NOTE: opcodes aren't fully metered, so the gas used, and so the fee paid, is only a lower bound of
+// what the specification charges. Execution is still bounded by the gas limit
+pub fn transact<D: Database>(
+    tx: &Transaction,
+    block: &Block,
+    state: &mut D,
+    native_precompiles: &NativePrecompiles,
+) -> Result<Receipt, TransactionError> {
+    let (receipt, _) = execute(tx, block, state, native_precompiles)?;
+    Ok(receipt)
+}
+
+// As transact, also returning every account and storage slot the transaction accessed
+pub(crate) fn execute<D: Database>(
+    tx: &Transaction,
+    block: &Block,
+    state: &mut D,
+    native_precompiles: &NativePrecompiles,
+) -> Result<(Receipt, AccessSet), TransactionError> {
+    let checkpoint = state.checkpoint();
+    let result = apply_transaction(tx, block, state, native_precompiles);
+    match result {
+        Ok(_) => state.commit(checkpoint),
+        Err(_) => state.revert(checkpoint),
+    }
+    result
+}
+
+fn apply_transaction<D: Database>(
+    tx: &Transaction,
+    block: &Block,
+    state: &mut D,
+    native_precompiles: &NativePrecompiles,
+) -> Result<(Receipt, AccessSet), TransactionError> {
+    let intrinsic_gas = tx.validate(block, state)?;
+    let gas_price = tx.effective_gas_price(block.basefee);
+
+    let address = match tx.to {
+        TxKind::Call(to) => to,
+        TxKind::Create => create_address(tx.from, tx.nonce.into()),
+    };
+
+    // the sender, recipient and access list start warm, as does the coinbase since EIP-3651
+    let mut access_set = AccessSet::from_access_list(&tx.access_list);
+    access_set.charge_cold_access = true;
+    access_set.access_address(tx.from);
+    access_set.access_address(address);
+    if block.fork.is_enabled(Fork::Shanghai) {
+        access_set.access_address(block.coinbase);
+    }
+
+    // the gas is paid for up front and the nonce incremented, neither is undone if execution fails and
+    // nor are the authorizations. Blob gas is burnt straight away
+    debit(state, tx.from, U256::from(tx.gas_limit) * gas_price)?;
+    debit(state, tx.from, U256::from(tx.blob_gas()) * block.blob_basefee())?;
+    state.set_nonce(tx.from, tx.nonce + 1).map_err(TransactionError::database)?;
+    let authorization_refund = apply_authorizations(tx, block, state, &mut access_set)?;
+    let checkpoint = state.checkpoint();
+
+    let execution_gas = tx.gas_limit - intrinsic_gas.initial;
+    let call_data = hex::encode(&tx.data);
+
+    let (code, call_data) = match tx.to {
+        TxKind::Call(to) => {
+            let code = state.code(to).map_err(TransactionError::database)?;
+            // EIP-7702: calling an account which delegated runs its delegate's code
+            match delegated_address(&code) {
+                Some(delegate) if block.fork.is_enabled(Fork::Prague) => {
+                    access_set.access_address(delegate);
+                    (state.code(delegate).map_err(TransactionError::database)?, call_data)
+                }
+                _ => (code, call_data),
+            }
+        }
+        TxKind::Create => (tx.data.clone(), String::new()),
+    };
+
+    debit(state, tx.from, tx.value)?;
+    credit(state, address, tx.value)?;
+
+    let collision = match tx.to {
+        TxKind::Create => has_contract(state, address).map_err(TransactionError::database)?,
+        TxKind::Call(_) => false,
+    };
+    let res = if collision {
+        // EIP-684: creating a contract where one already is fails, consuming all the gas
+        EvmResult {
+            stack: Vec::new(),
+            success: false,
+            error: Some(EvmError::CreateCollision),
+            logs: Vec::new(),
+            access_set,
+            return_val: None,
+            gas_used: execution_gas,
+        }
+    } else {
+        if let TxKind::Create = tx.to {
+            // EIP-161: contracts start with a nonce of 1, which the initialisation code already sees
+            state.set_nonce(address, 1).map_err(TransactionError::database)?;
+        }
+        let mut context = Context::new(
+            address,
+            tx.from,
+            tx.from,
+            gas_price,
+            tx.value,
+            &call_data,
+            state,
+            false,
+        );
+        context.gas_limit = execution_gas;
+        context.access_set = access_set;
+        context.blob_versioned_hashes = tx.blob_versioned_hashes.clone();
+        context.native_precompiles = native_precompiles.clone();
+
+        evm(code, context, block.clone())
+    };
+    if let Some(EvmError::Database(err)) = res.error {
+        return Err(TransactionError::Database(err));
+    }
+
+    let mut success = res.success;
+    let mut gas_used = intrinsic_gas.initial + res.gas_used;
+    let mut output = res.return_val.unwrap_or_default();
+    let mut error = res.error;
+
+    if success {
+        if let TxKind::Create = tx.to {
+            match check_deployed_code(&output, execution_gas - res.gas_used, block.fork) {
+                Ok(deposit_cost) => {
+                    gas_used += deposit_cost;
+                    state
+                        .set_code(address, std::mem::take(&mut output))
+                        .map_err(TransactionError::database)?;
+                }
+                Err(err) => {
+                    success = false;
+                    gas_used = tx.gas_limit;
+                    error = Some(err);
+                }
+            }
+        }
+    }
+
+    if success {
+        state.commit(checkpoint);
+    } else {
+        state.revert(checkpoint);
+    }
+
+    // EIP-3529: at most a fifth of the gas used is refunded
+    gas_used -= authorization_refund.min(gas_used / MAX_REFUND_QUOTIENT);
+    gas_used = gas_used.max(intrinsic_gas.floor);
+
+    // leftover gas is refunded to the sender
+    let refund = tx.gas_limit - gas_used;
+    credit(state, tx.from, U256::from(refund) * gas_price)?;
+
+    // after London the base fee is burnt and only the tip goes to the coinbase
+    let basefee = if block.fork.is_enabled(Fork::London) {
+        block.basefee
+    } else {
+        U256::zero()
+    };
+    let fee = split_fee(gas_used, gas_price, basefee);
+    credit(state, block.coinbase, fee.tip)?;
+
+    let logs = if success { res.logs } else { Vec::new() };
+    let receipt = Receipt {
+        tx_type: tx.tx_type,
+        success,
+        gas_used,
+        // a block executing several transactions adds the gas used before this one
+        cumulative_gas_used: gas_used,
+        logs_bloom: Bloom::from_logs(&logs),
+        logs,
+        contract_address: match tx.to {
+            TxKind::Create if success => Some(address),
+            _ => None,
+        },
+        effective_gas_price: gas_price,
+        output,
+        error,
+    };
+
+    Ok((receipt, res.access_set))
+}
+
+// Balances are checked here rather than left to the database, so they fail the same way whatever it is
+fn debit<D: Database>(state: &mut D, address: H160, amount: U256) -> Result<(), TransactionError> {
+    let available = state.balance(address).map_err(TransactionError::database)?;
+    if available < amount {
+        return Err(TransactionError::InsufficientFunds {
+            required: amount,
+            available,
+        });
+    }
+    state.decrement_balance(address, amount).map_err(TransactionError::database)
+}
+
+// EIP-4844 and EIP-7691: a single transaction can use the whole block's blob space
+fn max_blobs_per_block(fork: Fork) -> usize {
+    if fork.is_enabled(Fork::Prague) {
+        9
+    } else {
+        6
+    }
+}
+
+fn credit<D: Database>(state: &mut D, address: H160, amount: U256) -> Result<(), TransactionError> {
+    let balance = state.balance(address).map_err(TransactionError::database)?;
+    if balance.checked_add(amount).is_none() {
+        return Err(TransactionError::BalanceOverflow { address });
+    }
+    state.increment_balance(address, amount).map_err(TransactionError::database)
+}
+
+// EIP-7702: sets the code of each authority to delegate to the authorized address, returning the refund for
+// authorities which already existed. Invalid authorizations are skipped rather than failing the transaction
+fn apply_authorizations<D: Database>(
+    tx: &Transaction,
+    block: &Block,
+    state: &mut D,
+    access_set: &mut AccessSet,
+) -> Result<u64, TransactionError> {
+    let mut refund = 0;
+
+    for authorization in &tx.authorization_list {
+        if !authorization.chain_id.is_zero() && authorization.chain_id != block.chainid {
+            continue;
+        }
+        if authorization.nonce == u64::MAX {
+            continue;
+        }
+        let authority = match authorization.authority() {
+            Some(authority) => authority,
+            None => continue,
+        };
+
+        access_set.access_address(authority);
+
+        // only accounts without code, or which already delegated, can delegate
+        let code = state.code(authority).map_err(TransactionError::database)?;
+        if !code.is_empty() && delegated_address(&code).is_none() {
+            continue;
+        }
+        let nonce = state.nonce(authority).map_err(TransactionError::database)?;
+        if nonce != authorization.nonce {
+            continue;
+        }
+
+        let balance = state.balance(authority).map_err(TransactionError::database)?;
+        if nonce != 0 || !balance.is_zero() || !code.is_empty() {
+            refund += PER_EMPTY_ACCOUNT_COST - PER_AUTH_BASE_COST;
+        }
+
+        // delegating to the zero address clears the delegation
+        let code = if authorization.address.is_zero() {
+            Vec::new()
+        } else {
+            delegation_designator(authorization.address)
+        };
+        state.set_code(authority, code).map_err(TransactionError::database)?;
+        state.set_nonce(authority, nonce + 1).map_err(TransactionError::database)?;
+    }
+
+    Ok(refund)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::Convert;
+    use crate::precompiles::NativeOutput;
+use crate::state::State;
+    use k256::ecdsa::SigningKey;
+
+    const GAS_PRICE: u64 = 10;
+
+    // Without native precompiles, which most tests don't need
+    fn transact<D: Database>(
+        tx: &Transaction,
+        block: &Block,
+        state: &mut D,
+    ) -> Result<Receipt, TransactionError> {
+        super::transact(tx, block, state, &NativePrecompiles::new())
+    }
+
+    fn sender() -> H160 {
+        H160::repeat_byte(0x11)
+    }
+
+    fn recipient() -> H160 {
+        H160::repeat_byte(0x22)
+    }
+
+    fn block() -> Block {
+        Block {
+            coinbase: H160::repeat_byte(0x33),
+            ..Default::default()
+        }
+    }
+
+    fn tx(to: TxKind, value: u64) -> Transaction {
+        Transaction {
+            tx_type: TxType::Legacy,
+            from: sender(),
+            to,
+            value: value.into(),
+            data: Vec::new(),
+            gas_limit: 21000,
+            fees: TxFees::Legacy {
+                gas_price: GAS_PRICE.into(),
+            },
+            nonce: 0,
+            access_list: Vec::new(),
+            max_fee_per_blob_gas: U256::zero(),
+            blob_versioned_hashes: Vec::new(),
+            authorization_list: Vec::new(),
+        }
+    }
+
+    fn state(balance: u64) -> State {
+        let mut state = State::new();
+        state.add_account(sender(), balance.into(), Vec::new());
+        state
+    }
+
+    fn assert_unchanged(state: &State, balance: u64) {
+        assert_eq!(state.balance(sender()).unwrap(), balance.into());
+        assert_eq!(state.nonce(sender()).unwrap(), 0);
+        assert!(state.get_account(block().coinbase).is_none());
+    }
+
+    #[test]
+    fn transfers_the_value_and_pays_for_the_gas() {
+        let mut state = state(1_000_000);
+
+        let receipt = transact(&tx(TxKind::Call(recipient()), 100), &block(), &mut state).unwrap();
+
+        assert!(receipt.success);
+        assert_eq!(receipt.gas_used, 21000);
+        assert_eq!(state.balance(sender()).unwrap(), (1_000_000 - 100 - 21000 * GAS_PRICE).into());
+        assert_eq!(state.nonce(sender()).unwrap(), 1);
+        assert_eq!(state.balance(recipient()).unwrap(), 100.into());
+        assert_eq!(state.balance(block().coinbase).unwrap(), (21000 * GAS_PRICE).into());
+    }
+
+    #[test]
+    fn failed_execution_keeps_the_fee_and_nonce() {
+        let mut state = state(1_000_000);
+        // REVERT
+        state.add_account(recipient(), 0.into(), hex::decode("60006000fd").unwrap());
+        let mut tx = tx(TxKind::Call(recipient()), 100);
+        tx.gas_limit = 30000;
+
+        let receipt = transact(&tx, &block(), &mut state).unwrap();
+
+        assert!(!receipt.success);
+        let fee = receipt.gas_used * GAS_PRICE;
+        assert_eq!(state.balance(sender()).unwrap(), (1_000_000 - fee).into());
+        assert_eq!(state.nonce(sender()).unwrap(), 1);
+        assert_eq!(state.balance(recipient()).unwrap(), 0.into());
+        assert_eq!(state.balance(block().coinbase).unwrap(), fee.into());
+    }
+
+    #[test]
+    fn creates_a_contract() {
+        let mut state = state(1_000_000_000);
+        let mut tx = tx(TxKind::Create, 0);
+        // MSTORE8(0, 0xaa) then RETURN(0, 1)
+        tx.data = hex::decode("60aa60005360016000f3").unwrap();
+        tx.gas_limit = 100_000;
+
+        let receipt = transact(&tx, &block(), &mut state).unwrap();
+
+        let address = create_address(sender(), 0.into());
+        assert!(receipt.success);
+        assert_eq!(receipt.contract_address, Some(address));
+        assert_eq!(state.code(address).unwrap(), vec![0xaa]);
+        assert_eq!(state.nonce(address).unwrap(), 1);
+    }
+
+    #[test]
+    fn initialisation_code_sees_the_contract_nonce_of_one() {
+        let mut state = state(1_000_000_000);
+        let mut tx = tx(TxKind::Create, 0);
+        // CREATE(0, 0, 0)
+        tx.data = hex::decode("600060006000f0").unwrap();
+        tx.gas_limit = 100_000;
+
+        let receipt = transact(&tx, &block(), &mut state).unwrap();
+
+        let address = create_address(sender(), 0.into());
+        assert!(receipt.success);
+        assert_eq!(state.nonce(address).unwrap(), 2);
+        assert_eq!(state.nonce(create_address(address, 1.into())).unwrap(), 1);
+    }
+
+    #[test]
+    fn create_collision_consumes_all_the_gas() {
+        let mut state = state(1_000_000_000);
+        state.set_nonce(create_address(sender(), 0.into()), 1).unwrap();
+        let mut tx = tx(TxKind::Create, 0);
+        tx.gas_limit = 100_000;
+
+        let receipt = transact(&tx, &block(), &mut state).unwrap();
+
+        assert!(!receipt.success);
+        assert_eq!(receipt.gas_used, 100_000);
+        assert!(matches!(receipt.error, Some(EvmError::CreateCollision)));
+        assert_eq!(receipt.contract_address, None);
+        assert_eq!(state.nonce(sender()).unwrap(), 1);
+    }
+
+    #[test]
+    fn rejects_a_nonce_mismatch() {
+        let mut state = state(1_000_000);
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.nonce = 1;
+
+        let err = transact(&tx, &block(), &mut state).unwrap_err();
+
+        assert_eq!(err, TransactionError::NonceMismatch { expected: 0, actual: 1 });
+        assert_unchanged(&state, 1_000_000);
+    }
+
+    #[test]
+    fn rejects_a_sender_with_the_max_nonce() {
+        let mut state = state(1_000_000);
+        state.set_nonce(sender(), u64::MAX).unwrap();
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.nonce = u64::MAX;
+
+        let err = transact(&tx, &block(), &mut state).unwrap_err();
+
+        assert_eq!(err, TransactionError::NonceMax);
+        assert_eq!(state.nonce(sender()).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn rejects_insufficient_funds() {
+        let balance = 21000 * GAS_PRICE + 99;
+        let mut state = state(balance);
+
+        let err = transact(&tx(TxKind::Call(recipient()), 100), &block(), &mut state).unwrap_err();
+
+        assert_eq!(
+            err,
+            TransactionError::InsufficientFunds {
+                required: (balance + 1).into(),
+                available: balance.into(),
+            }
+        );
+        assert_unchanged(&state, balance);
+    }
+
+    #[test]
+    fn rejects_a_gas_limit_below_the_intrinsic_gas() {
+        let mut state = state(1_000_000);
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.gas_limit = 20999;
+
+        let err = transact(&tx, &block(), &mut state).unwrap_err();
+
+        assert_eq!(err, TransactionError::IntrinsicGasTooLow { required: 21000, limit: 20999 });
+        assert_unchanged(&state, 1_000_000);
+    }
+
+    #[test]
+    fn balance_overflow_leaves_the_state_unchanged() {
+        let mut state = state(1_000_000);
+        state.add_account(recipient(), U256::MAX, Vec::new());
+
+        let err = transact(&tx(TxKind::Call(recipient()), 1), &block(), &mut state).unwrap_err();
+
+        assert_eq!(err, TransactionError::BalanceOverflow { address: recipient() });
+        assert_unchanged(&state, 1_000_000);
+        assert_eq!(state.balance(recipient()).unwrap(), U256::MAX);
+    }
+
+    #[test]
+    fn native_precompiles_are_available_to_the_transaction() {
+        let mut state = state(1_000_000_000);
+        // CALL 0x44, then INVALID unless the call succeeded
+        let code = hex::decode("6000600060006000600060445af1601257fe5b00").unwrap();
+        state.add_account(recipient(), 0.into(), code);
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.gas_limit = 100_000;
+        let mut native_precompiles = NativePrecompiles::new();
+        native_precompiles.register(H160::from_low_u64_be(0x44), |_: &[u8], _| NativeOutput {
+            output: Vec::new(),
+            gas_used: 0,
+            success: false,
+        });
+
+        assert!(transact(&tx, &block(), &mut state).unwrap().success);
+        tx.nonce = 1;
+        let receipt = super::transact(&tx, &block(), &mut state, &native_precompiles).unwrap();
+        assert!(!receipt.success);
+    }
+
+    #[test]
+    fn endless_loops_run_out_of_gas() {
+        let mut state = state(1_000_000_000);
+        // JUMPDEST, JUMP(0)
+        state.add_account(recipient(), 0.into(), hex::decode("5b600056").unwrap());
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.gas_limit = 100_000;
+
+        let receipt = transact(&tx, &block(), &mut state).unwrap();
+
+        assert!(!receipt.success);
+        assert_eq!(receipt.gas_used, 100_000);
+    }
+
+    #[test]
+    fn access_list_warms_its_slots() {
+        let mut state = state(1_000_000_000);
+        // SLOAD(0)
+        state.add_account(recipient(), 0.into(), hex::decode("600054").unwrap());
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.gas_limit = 100_000;
+
+        let cold = transact(&tx, &block(), &mut state).unwrap();
+
+        tx.nonce = 1;
+        tx.tx_type = TxType::AccessList;
+        tx.access_list = vec![AccessListItem {
+            address: recipient(),
+            storage_keys: vec![H256::zero()],
+        }];
+        let warm = transact(&tx, &block(), &mut state).unwrap();
+        // the list costs 2400 for the address and 1900 for the slot up front, saving the cold surcharge
+        assert_eq!(warm.gas_used - cold.gas_used, 2400 + 1900 - (2100 - 100));
+    }
+
+    fn dynamic_fees() -> TxFees {
+        TxFees::Dynamic {
+            max_fee_per_gas: GAS_PRICE.into(),
+            max_priority_fee_per_gas: GAS_PRICE.into(),
+        }
+    }
+
+    fn blob_tx() -> Transaction {
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.tx_type = TxType::Blob;
+        tx.fees = dynamic_fees();
+        tx.gas_limit = 100_000;
+        tx.max_fee_per_blob_gas = 1.into();
+        tx.blob_versioned_hashes = vec![H256::repeat_byte(1)];
+        tx
+    }
+
+    fn rejects(tx: &Transaction, err: TransactionError) {
+        let mut state = state(1_000_000_000);
+
+        assert_eq!(transact(tx, &block(), &mut state).unwrap_err(), err);
+        assert_unchanged(&state, 1_000_000_000);
+    }
+
+    #[test]
+    fn receipts_have_the_transaction_type() {
+        let mut state = state(1_000_000_000);
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.tx_type = TxType::AccessList;
+
+        // even without any entries in its access list
+        assert_eq!(transact(&tx, &block(), &mut state).unwrap().tx_type, TxType::AccessList);
+    }
+
+    #[test]
+    fn rejects_fields_the_type_does_not_have() {
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.access_list = vec![AccessListItem {
+            address: recipient(),
+            storage_keys: Vec::new(),
+        }];
+        rejects(&tx, TransactionError::FieldsDontMatchType(TxType::Legacy));
+
+        let mut tx = tx.clone();
+        tx.tx_type = TxType::DynamicFee;
+        rejects(&tx, TransactionError::FieldsDontMatchType(TxType::DynamicFee));
+    }
+
+    #[test]
+    fn rejects_types_before_their_fork() {
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.tx_type = TxType::DynamicFee;
+        tx.fees = dynamic_fees();
+        let block = Block {
+            fork: Fork::Berlin,
+            ..block()
+        };
+
+        let err = transact(&tx, &block, &mut state(1_000_000_000)).unwrap_err();
+
+        assert_eq!(err, TransactionError::TypeNotEnabled(TxType::DynamicFee));
+    }
+
+    #[test]
+    fn set_code_needs_authorizations_and_dynamic_fees() {
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.tx_type = TxType::SetCode;
+        rejects(&tx, TransactionError::FieldsDontMatchType(TxType::SetCode));
+
+        tx.fees = dynamic_fees();
+        rejects(&tx, TransactionError::EmptyAuthorizationList);
+    }
+
+    fn authorization(chain_id: u64, nonce: u64) -> Authorization {
+        let mut authorization = Authorization {
+            chain_id: chain_id.into(),
+            address: H160::repeat_byte(0xaa),
+            nonce,
+            y_parity: 0,
+            r: U256::zero(),
+            s: U256::zero(),
+        };
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let (signature, recovery_id) = key
+            .sign_prehash_recoverable(authorization.signing_hash().as_bytes())
+            .unwrap();
+        authorization.r = U256::from_big_endian(&signature.r().to_bytes());
+        authorization.s = U256::from_big_endian(&signature.s().to_bytes());
+        authorization.y_parity = recovery_id.to_byte();
+        authorization
+    }
+
+    fn set_code_tx(authorization_list: Vec<Authorization>) -> Transaction {
+        let mut tx = tx(TxKind::Call(recipient()), 0);
+        tx.tx_type = TxType::SetCode;
+        tx.fees = dynamic_fees();
+        tx.gas_limit = 200_000;
+        tx.authorization_list = authorization_list;
+        tx
+    }
+
+    fn delegates(state: &State, authority: H160) -> bool {
+        delegated_address(&state.code(authority).unwrap()) == Some(H160::repeat_byte(0xaa))
+    }
+
+    #[test]
+    fn authorizations_are_checked_against_the_chain_id() {
+        let authority = authorization(0, 0).authority().unwrap();
+        let block = Block {
+            chainid: 1.into(),
+            ..block()
+        };
+
+        for (chain_id, valid) in [(0, true), (1, true), (2, false)] {
+            let mut state = state(1_000_000_000);
+            let tx = set_code_tx(vec![authorization(chain_id, 0)]);
+
+            assert!(transact(&tx, &block, &mut state).unwrap().success);
+            assert_eq!(delegates(&state, authority), valid, "chain id {}", chain_id);
+            assert_eq!(state.nonce(authority).unwrap(), valid as u64);
+        }
+    }
+
+    #[test]
+    fn authorizations_with_the_max_nonce_are_skipped() {
+        let authority = authorization(0, 0).authority().unwrap();
+        let mut state = state(1_000_000_000);
+        state.set_nonce(authority, u64::MAX).unwrap();
+
+        let tx = set_code_tx(vec![authorization(0, u64::MAX)]);
+        assert!(transact(&tx, &block(), &mut state).unwrap().success);
+
+        assert!(!delegates(&state, authority));
+        assert_eq!(state.nonce(authority).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn existing_authorities_are_refunded() {
+        // BALANCE of ten cold accounts, so that the refund isn't capped at a fifth of the gas used
+        let code = (1..=10).map(|i| format!("6110{:02x}3150", i)).collect::<String>();
+        let gas_used = |existing: bool| {
+            let mut state = state(1_000_000_000);
+            state.add_account(recipient(), 0.into(), hex::decode(&code).unwrap());
+            if existing {
+                state.add_account(authorization(0, 0).authority().unwrap(), 1.into(), Vec::new());
+            }
+            let tx = set_code_tx(vec![authorization(0, 0)]);
+            transact(&tx, &block(), &mut state).unwrap().gas_used
+        };
+
+        assert!(gas_used(false) / MAX_REFUND_QUOTIENT > PER_EMPTY_ACCOUNT_COST - PER_AUTH_BASE_COST);
+        assert_eq!(gas_used(false) - gas_used(true), PER_EMPTY_ACCOUNT_COST - PER_AUTH_BASE_COST);
+    }
+
+    #[test]
+    fn blob_transactions_pay_for_blob_gas() {
+        let mut state = state(1_000_000_000);
+        // SSTORE(0, BLOBHASH(0))
+        state.add_account(recipient(), 0.into(), hex::decode("600049600055").unwrap());
+        let tx = blob_tx();
+
+        let receipt = transact(&tx, &block(), &mut state).unwrap();
+
+        assert!(receipt.success);
+        assert_eq!(receipt.tx_type, TxType::Blob);
+        let fee = receipt.gas_used * GAS_PRICE + 131072;
+        assert_eq!(state.balance(sender()).unwrap(), (1_000_000_000 - fee).into());
+        assert_eq!(state.storage(recipient(), 0.into()).unwrap(), H256::repeat_byte(1).to_u256());
+    }
+
+    #[test]
+    fn rejects_invalid_blob_transactions() {
+        let mut tx = blob_tx();
+        tx.to = TxKind::Create;
+        rejects(&tx, TransactionError::BlobCreate);
+
+        let mut tx = blob_tx();
+        tx.blob_versioned_hashes = Vec::new();
+        rejects(&tx, TransactionError::NoBlobs);
+
+        let mut tx = blob_tx();
+        tx.blob_versioned_hashes = vec![H256::repeat_byte(1); 10];
+        rejects(&tx, TransactionError::TooManyBlobs { count: 10, max: 9 });
+
+        let mut tx = blob_tx();
+        tx.blob_versioned_hashes = vec![H256::repeat_byte(2)];
+        rejects(&tx, TransactionError::InvalidBlobVersionedHash(H256::repeat_byte(2)));
+
+        let mut tx = blob_tx();
+        tx.max_fee_per_blob_gas = 0.into();
+        rejects(&tx, TransactionError::BlobFeeCapBelowBlobBaseFee);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use primitive_types::{H160, H256, U256};
+use rlp::RlpStream;
+use sha3::{Digest, Keccak256};
+
+// keccak256(rlp("")), the root of a trie without any entries
+pub const EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e, 0x5b,
+    0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+// keccak256(""), the code hash of accounts without code
+pub const EMPTY_CODE_HASH: H256 = H256([
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0, 0xe5,
+    0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+]);
+
+// The root of the Merkle Patricia Trie holding the given entries. Keys are used as they are, so for the
+// secure tries of the state they have to be hashed first. A key given more than once keeps its last
+// value, as when inserting into a trie
+pub fn trie_root(entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> H256 {
+    // encode_node relies on the keys being sorted and unique
+    let entries: BTreeMap<Vec<u8>, Vec<u8>> = entries
+        .into_iter()
+        .map(|(key, value)| (to_nibbles(&key), value))
+        .collect();
+    if entries.is_empty() {
+        return EMPTY_ROOT;
+    }
+    let entries: Vec<(Vec<u8>, Vec<u8>)> = entries.into_iter().collect();
+
+    // unlike other nodes the root is hashed even when its encoding is shorter than a hash
+    let node = encode_node(&entries, 0);
+    H256::from_slice(&Keccak256::digest(node))
+}
+
+// Storage slots are keyed by the hash of the slot, and slots set to zero are left out
+pub fn storage_root(storage: impl IntoIterator<Item = (U256, U256)>) -> H256 {
+    trie_root(storage.into_iter().filter(|(_, value)| !value.is_zero()).map(|(key, value)| {
+        let mut key_bytes = [0; 32];
+        key.to_big_endian(&mut key_bytes);
+        (keccak(&key_bytes), rlp::encode(&value).to_vec())
+    }))
+}
+
+// The leaf of an account in the state trie, which is keyed by the hash of the address
+pub fn encode_account(nonce: u64, balance: U256, storage_root: H256, code_hash: H256) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(4);
+    stream.append(&nonce);
+    stream.append(&balance);
+    stream.append(&storage_root);
+    stream.append(&code_hash);
+    stream.out().to_vec()
+}
+
+pub fn account_key(address: H160) -> Vec<u8> {
+    keccak(address.as_bytes())
+}
+
+fn keccak(data: &[u8]) -> Vec<u8> {
+    Keccak256::digest(data).to_vec()
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|byte| [byte >> 4, byte & 0x0f]).collect()
+}
+
+// Entries are sorted by key, and every key matches from the start up to depth
+fn encode_node(entries: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Vec<u8> {
+    if let [(key, value)] = entries {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(&key[depth..], true));
+        stream.append(value);
+        return stream.out().to_vec();
+    }
+
+    // as the keys are sorted, the nibbles shared by all of them are those shared by the first and last
+    let first = &entries[0].0;
+    let last = &entries[entries.len() - 1].0;
+    let shared = first[depth..]
+        .iter()
+        .zip(&last[depth..])
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    if shared > 0 {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(&first[depth..depth + shared], false));
+        append_reference(&mut stream, &encode_node(entries, depth + shared));
+        return stream.out().to_vec();
+    }
+
+    // a key ending here sorts first, and its value is kept in the branch itself
+    let (value, mut rest) = if first.len() == depth {
+        (Some(&entries[0].1), &entries[1..])
+    } else {
+        (None, entries)
+    };
+
+    let mut stream = RlpStream::new_list(17);
+    for nibble in 0..16 {
+        let count = rest.iter().take_while(|(key, _)| key[depth] == nibble).count();
+        let (children, remaining) = rest.split_at(count);
+        if children.is_empty() {
+            stream.append_empty_data();
+        } else {
+            append_reference(&mut stream, &encode_node(children, depth + 1));
+        }
+        rest = remaining;
+    }
+    match value {
+        Some(value) => stream.append(value),
+        None => stream.append_empty_data(),
+    };
+    stream.out().to_vec()
+}
+
+// Nodes shorter than a hash are embedded in their parent rather than referenced by hash
+fn append_reference(stream: &mut RlpStream, node: &[u8]) {
+    if node.len() < 32 {
+        stream.append_raw(node, 1);
+    } else {
+        stream.append(&keccak(node));
+    }
+}
+
+// Packs nibbles into bytes, with a first nibble flagging whether the node is a leaf and the path is odd
+fn hex_prefix(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 } + nibbles.len() as u8 % 2;
+
+    let mut bytes = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        bytes.push(flag << 4 | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        bytes.push(flag << 4);
+        nibbles
+    };
+    bytes.extend(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(entries: &[(&str, &str)]) -> H256 {
+        trie_root(entries.iter().map(|(key, value)| (bytes(key), bytes(value))))
+    }
+
+    // Vectors give keys and values as strings, or as hex when prefixed by 0x
+    fn bytes(data: &str) -> Vec<u8> {
+        match data.strip_prefix("0x") {
+            Some(hex) => hex::decode(hex).unwrap(),
+            None => data.as_bytes().to_vec(),
+        }
+    }
+
+    fn h256(hex: &str) -> H256 {
+        H256::from_slice(&hex::decode(hex).unwrap())
+    }
+
+    #[test]
+    fn empty_trie_has_the_empty_root() {
+        assert_eq!(trie_root(Vec::new()), EMPTY_ROOT);
+        assert_eq!(EMPTY_ROOT, H256::from_slice(&keccak(&rlp::encode(&""))));
+        assert_eq!(storage_root([(1.into(), U256::zero())]), EMPTY_ROOT);
+    }
+
+    // From trieanyorder.json and trietest.json of the ethereum/tests repository
+    #[test]
+    fn matches_the_trie_test_vectors() {
+        let vectors: [(&[(&str, &str)], &str); 7] = [
+            (
+                &[("A", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")],
+                "d23786fb4a010da3ce639d66d5e904a11dbc02746d1ce25029e53290cabf28ab",
+            ),
+            (
+                &[("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")],
+                "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3",
+            ),
+            (
+                &[("do", "verb"), ("horse", "stallion"), ("doge", "coin"), ("dog", "puppy")],
+                "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84",
+            ),
+            (
+                &[("foo", "bar"), ("food", "bass")],
+                "17beaa1648bafa633cda809c90c04af50fc8aed3cb40d16efbddee6fdf63c4c3",
+            ),
+            (
+                &[("be", "e"), ("dog", "puppy"), ("bed", "d")],
+                "3f67c7a47520f79faa29255d2d3c084a7a6df0453116ed7232ff10277a8be68b",
+            ),
+            (
+                &[("test", "test"), ("te", "testy")],
+                "8452568af70d8d140f58d941338542f645fcca50094b20f3c3d8c3df49337928",
+            ),
+            (
+                &[("0x0045", "0x0123456789"), ("0x4500", "0x9876543210")],
+                "285505fcabe84badc8aa310e2aae17eddc7d120aabec8a476902c8184b3a3503",
+            ),
+        ];
+
+        for (entries, expected) in vectors {
+            assert_eq!(root(entries), h256(expected), "{:?}", entries);
+        }
+    }
+
+    #[test]
+    fn repeated_keys_keep_the_last_value() {
+        let repeated = root(&[("dog", "cat"), ("doge", "coin"), ("dog", "puppy")]);
+
+        assert_eq!(repeated, root(&[("doge", "coin"), ("dog", "puppy")]));
+    }
+}